@@ -1,18 +1,60 @@
 use bracket_lib::prelude::*;
 use lazy_static::lazy_static;
-use image;
 use image::GenericImageView; // 引入GenericImageView trait
 use std::fs;
-use std::path::Path;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::collections::VecDeque;
+use std::io::{BufReader, Write};
+use std::process::{Command, Stdio};
+use std::time::{SystemTime, UNIX_EPOCH};
+use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink, Source};
+use rodio::source::Buffered;
 
 // 游戏三种模式
 enum GameMode {
     Menu,
     Playing,
     End,
+    Demo, // 菜单闲置时的自动演示
+    Tutorial, // 第一次启动时的新手教程
+    HotSeat, // 双人轮流模式的换人/结果界面
+    Photo, // 拍照模式：本局暂停，可以平移和放大冻结的画面
+}
+
+// 自适应难度：记下最近几局的存活时间，每局开始时据此把难度等级往上或往下推一点
+struct AdaptiveDifficulty {
+    recent_runs: VecDeque<f32>, // 最近几局的存活时间（毫秒），最新的在最后
+    level: f32, // -1 最松到 1 最紧，0 和普通难度一样
+    pending: bool, // 上次开局之后有新的一局结束，下次开局要重新调整
+}
+
+// 拍照模式：进入时把当前画面按格子冻结下来，之后只改变看它的位置和倍率
+struct PhotoMode {
+    cells: Vec<(RGB, bool)>, // 冻结的画面，和截图用的格子一样
+    pan_x: f32, // 画面中心相对原来中心的偏移（格）
+    pan_y: f32,
+    zoom: f32,
+    message: Option<String>, // 保存结果的提示
+}
+
+// 双人轮流模式：两人先后玩同一个种子的关卡，分高者胜
+struct HotSeat {
+    turn: usize,
+    scores: [i32; 2],
+    seed: u64,
+}
+
+// 新手教程的步骤
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TutorialStep {
+    Flap,
+    Gaps,
+    Done,
 }
 
 // 背景样式
+#[derive(Clone, Copy, PartialEq, Eq)]
 enum BackgroundStyle {
     Stars,
     Clouds,
@@ -20,20 +62,371 @@ enum BackgroundStyle {
 }
 
 // 玩家样式
+#[derive(Clone, Copy, PartialEq, Eq)]
 enum PlayerStyle {
     Dragon,
     Bird,
     Duck,
 }
 
+// 难度
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+    Adaptive, // 以普通难度为基础，根据最近几局的存活时间自动调松或调紧
+}
+
+// 玩法：连击模式下每干净地穿过一根管道就加速，擦边则速度归零；
+// 沙盒模式没有管道也不会死，只能自由飞行，不计分；
+// 禅模式的缺口和速度始终保持开局时的样子，不随分数变难
+// 马拉松模式每隔几根管道存一个检查点，死了回到最近的检查点，重试次数有限
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum RunMode {
+    Classic,
+    Combo,
+    Sandbox,
+    Zen,
+    Marathon,
+}
+
+// 背景滚动方向
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ScrollDirection {
+    RightToLeft,
+    LeftToRight,
+    Vertical, // 缓慢向下漂移
+}
+
+// 自动扇翅膀辅助：轻度只在快撞到地面或缺口下沿时帮忙，完全则基本自动驾驶
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FlapAssist {
+    Off,
+    Light,
+    Full,
+}
+
+// 计分方式：按分数，或者按存活时间
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Scoring {
+    Points,
+    Time,
+}
+
+// 界面语言
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Language {
+    English,
+    Spanish,
+}
+
+// 菜单和 HUD 上的文字，按当前语言从翻译表里取；带 {} 的用 fill 填入数值
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Text {
+    PressToFlap,
+    PhotoModeHint,
+    SandboxHint,
+    HudLine,
+    HudPlayer,
+    HudLives,
+    HudShield,
+    HudSpeed,
+    HudCombo,
+    AchievementUnlocked,
+    TutorialFlap,
+    TutorialGaps,
+    TutorialAvoid,
+    TutorialDone,
+    TutorialScore,
+    SkipTutorial,
+    DemoHint,
+    HotSeatScored,
+    HotSeatReady,
+    PressSpaceToStart,
+    Player1Wins,
+    Player2Wins,
+    Tie,
+    HotSeatResult,
+    PlayAgain,
+    MainMenuKey,
+    YouAreDead,
+    FinalScore,
+    HighScore,
+    LastAndBest,
+    WindowTooSmall,
+    WindowMinSize,
+    RecordedOn,
+    PlayAgainRetry,
+    ReallyQuit,
+    QuitGameKey,
+    SaveScreenshotKey,
+    RewindKeys,
+    ShareCodeLine,
+    Saved,
+    ScreenshotFailed,
+    ReplayFailed,
+    PhotoFailed,
+    PhotoHint,
+    StartGame,
+    BackgroundStyle,
+    PlayerStyle,
+    ObstacleDistance,
+    Difficulty,
+    Mode,
+    Leaderboard,
+    Achievements,
+    Options,
+    TwoPlayers,
+    EnterShareCode,
+    QuitGame,
+    MenuShortcuts,
+    Stars,
+    Clouds,
+    Mountains,
+    Back,
+    SelectBackground,
+    SelectPlayer,
+    CoinsTotal,
+    LockedSkin,
+    UnlockFor,
+    NeedMoreCoins,
+    CurrentSpaces,
+    AdjustHint,
+    Pace,
+    Relaxed,
+    Moderate,
+    Frantic,
+    NoScores,
+    PipesUnit,
+    SwitchBoardHint,
+    AchievementsCount,
+    MusicVolume,
+    Lives,
+    FlapCooldown,
+    PipeStyle,
+    StartGrace,
+    Auto,
+    BackgroundScroll,
+    Gravity,
+    UpcomingPipes,
+    HighlightColor,
+    MenuTextColor,
+    SpeedTrails,
+    ReducedMotion,
+    Scoring,
+    GapFeathering,
+    FlapAssist,
+    NearPipeSlowdown,
+    HudPanel,
+    Flap,
+    SpriteScaling,
+    IdealPath,
+    Language,
+    PipeSpacing,
+    Varied,
+    Fixed,
+    PassedPipes,
+    CheckpointReached,
+    CheckpointRespawn,
+    HudCheckpoint,
+    BackgroundBrightness,
+    DimSprites,
+    SeedLine,
+    SeedCopied,
+    SeedCopyFailed,
+    InputLogFailed,
+    GapCurve,
+    SafeZone,
+    Foreground,
+    QuickRestart,
+    SoundVolume,
+    FlapKey,
+    FastFallKey,
+    KeySpace,
+    KeyUp,
+    KeyDown,
+    KeyShift,
+    KeyCtrl,
+    On,
+    Off,
+    Shown,
+    Hidden,
+    Classic,
+    HoldToClimb,
+    Smooth,
+    PixelArt,
+    ResetProgress,
+    ReallyReset,
+    ShareCodeHint,
+    ProfileOption,
+    Profiles,
+    NewProfile,
+    ProfilesHint,
+    ReallyDeleteProfile,
+    ProfileNameHint,
+    ProfileNameInvalid,
+    ProfileExists,
+    CannotDeleteDefault,
+    Easy,
+    Normal,
+    Hard,
+    Adaptive,
+    Combo,
+    Sandbox,
+    Zen,
+    Marathon,
+    Dragon,
+    Bird,
+    Duck,
+    Yellow,
+    White,
+    Cyan,
+    Magenta,
+    Orange,
+    Green,
+    Gray,
+    Solid,
+    Shade,
+    Hash,
+    Brick,
+    Linear,
+    Quadratic,
+    Snappy,
+    RightToLeft,
+    LeftToRight,
+    Vertical,
+    Light,
+    Full,
+    Points,
+    Time,
+    CasualBoard,
+    HardcoreBoard,
+    CasualTimeBoard,
+    HardcoreTimeBoard,
+    ZenBoard,
+    AchievementFirstPipe,
+    AchievementFirstPipeGoal,
+    AchievementPipes10,
+    AchievementPipes10Goal,
+    AchievementPipes50,
+    AchievementPipes50Goal,
+    AchievementScore50,
+    AchievementScore50Goal,
+    AchievementPerfect5,
+    AchievementPerfect5Goal,
+    AchievementCoins10,
+    AchievementCoins10Goal,
+    AchievementHardPipes20,
+    AchievementHardPipes20Goal,
+}
+
+// 排行榜分类：硬核榜只收无辅助的困难局，其余都进休闲榜；按时间计分的局另有两个榜，禅模式单独一个榜
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum LeaderboardKind {
+    Casual,
+    Hardcore,
+    CasualTime,
+    HardcoreTime,
+    Zen,
+}
+
 struct DefaultParameters {
     screen_width: i32,
     screen_height: i32,
     player_width: i32,
     player_height: i32,
+    player_start_fraction: f32,
+    ground_height: i32,
     frame_duration: f32,
+    min_frame_time_ms: f32,
+    max_frame_time_ms: f32,
     obstacle_speed: f32,
     background_speed: f32,
+    demo_idle_ms: f32,
+    screenshot_scale: u32,
+    rewind_ms: f32,
+    rewind_buffer_len: usize,
+    input_log_ms: f32,
+    respawn_invuln_ms: f32,
+    near_miss_distance: i32,
+    near_miss_bonus: i32,
+    letterbox_color: RGB,
+    min_cell_pixels: u32,
+    tile_pixels: u32,
+    combo_speed_step: f32,
+    combo_max_speed: f32,
+    combo_fx_threshold: i32,
+    combo_fx_full: i32,
+    combo_fx_fade_ms: f32,
+    combo_fx_tint: f32,
+    combo_fx_pulse_ms: f32,
+    combo_fx_edge: i32,
+    chaos_start_score: i32,
+    chaos_full_score: i32,
+    chaos_scroll: f32,
+    chaos_tint: f32,
+    chaos_detail: f32,
+    double_gap_min_score: i32,
+    double_gap_chance: i32,
+    double_gap_size: i32,
+    double_gap_bar: i32,
+    coin_chance: i32,
+    wind_min_score: i32,
+    wind_chance: i32,
+    wind_width: i32,
+    wind_height: i32,
+    wind_strength: f32,
+    wind_particle_ms: f32,
+    toast_ms: f32,
+    gap_start: i32,
+    gap_min: i32,
+    gap_shrink_per_point: f32,
+    gap_curve_points: i32,
+    gap_curve_rows: i32,
+    gap_margin: i32,
+    trail_min_speed: f32,
+    trail_full_speed: f32,
+    trail_max_columns: i32,
+    flap_buffer_ms: f32,
+    fast_fall_factor: f32,
+    gif_scale: u32,
+    gif_max_frames: usize,
+    obstacle_pool_max: usize,
+    assist_margin: i32,
+    background_frame_ms: f32,
+    speed_blend_ms: f32,
+    min_reaction_ms: f32,
+    obstacle_width: i32,
+    music_layer_score: f32,
+    slowdown_factor: f32,
+    slowdown_blend_ms: f32,
+    adaptive_window: usize,
+    adaptive_short_ms: f32,
+    adaptive_long_ms: f32,
+    adaptive_step: f32,
+    adaptive_speed_range: f32,
+    adaptive_gap_range: i32,
+    flap_tap_fraction: f32,
+    flap_hold_boost: f32,
+    flap_hold_cap: f32,
+    flap_hold_max_ms: f32,
+    photo_pan_step: f32,
+    photo_max_zoom: f32,
+    world_top: i32,
+    world_bottom: i32,
+    ideal_path_pipes: usize,
+    safe_zone_opacity: f32,
+    warning_cells: i32,
+    flap_pitch_range: f32,
+    warning_blink_ms: f32,
+    spacing_jitter: i32,
+    checkpoint_pipes: i32,
+    checkpoint_retries: i32,
+    closing_min_score: i32,
+    closing_chance: i32,
+    closing_extra: i32,
+    closing_rate: f32,
 }
 
 lazy_static! {
@@ -42,30 +435,268 @@ lazy_static! {
         screen_height: 80,
         player_width: 14,
         player_height:14,
-        frame_duration: 75.0,
+        player_start_fraction: 0.5, // 玩家初始高度占屏幕高度的比例，0.5为居中
+        ground_height: 4, // 屏幕底部地面的高度
+        frame_duration: 75.0, // 普通难度下每隔多少毫秒走一步物理，玩家手感参数都是按这个步长调的
+        min_frame_time_ms: 1.0, // 帧时间限制在这个范围内，避免首帧或卡顿后物理和背景跳变
+        max_frame_time_ms: 100.0,
         obstacle_speed: 0.5,
         background_speed: 0.001,
+        demo_idle_ms: 15000.0, // 菜单闲置这么久后进入演示
+        screenshot_scale: 4, // 截图时每格放大成 4x4 像素
+        rewind_ms: 2000.0, // 死亡后倒带回到多久之前
+        rewind_buffer_len: 40, // 每个物理帧存一份快照，最多保留这么多份
+        input_log_ms: 5000.0, // 调试模式下逐帧记录最近这么长时间的输入
+        respawn_invuln_ms: 2000.0, // 丢命复活后的无敌时间
+        near_miss_distance: 2, // 离管道边缘不超过这么多格算擦边
+        near_miss_bonus: 1,
+        letterbox_color: RGB::from_u8(24, 24, 32), // 窗口比游戏区大时，四周空白的颜色
+        min_cell_pixels: 4, // 游戏区缩小后每格至少要有这么多像素，再小就看不清了，改为提示窗口太小
+        tile_pixels: 10, // 字体每格的像素数，窗口太小的提示也按这个大小画
+        combo_speed_step: 0.05, // 连击模式每次干净穿过增加的速度倍率
+        combo_max_speed: 2.0, // 连击模式的速度倍率上限
+        combo_fx_threshold: 5, // 连续干净穿过这么多根管道后开始出现连击特效
+        combo_fx_full: 20, // 连续干净穿过这么多根时特效最强
+        combo_fx_fade_ms: 400.0, // 连击断了以后特效在这么长时间内淡出
+        combo_fx_tint: 0.3, // 特效最强时整个背景往脉冲色混合的比例
+        combo_fx_pulse_ms: 1200.0, // 脉冲色的色相转一圈的时间，特效越强转得越快
+        combo_fx_edge: 4, // 特效最强时屏幕边缘光晕的宽度（格）
+        chaos_start_score: 25, // 分数达到这么多后画面开始逐渐变得紧张
+        chaos_full_score: 100, // 分数达到这么多时紧张感最强，之后不再增加
+        chaos_scroll: 0.6, // 紧张感最强时背景滚动加快的比例
+        chaos_tint: 0.15, // 紧张感最强时背景往红色混合的比例
+        chaos_detail: 0.04, // 紧张感最强时背景上出现光点的格子比例
+        double_gap_min_score: 20, // 分数达到这么多后才会出现双缺口管道
+        double_gap_chance: 10, // 每根新管道是双缺口的概率（百分比）
+        double_gap_size: 20, // 双缺口每个缺口的高度，比玩家高 6 格
+        double_gap_bar: 6, // 两个缺口之间横杆的厚度
+        coin_chance: 50, // 每根新管道的缺口里放一枚金币的概率（百分比）
+        wind_min_score: 15, // 分数达到这么多后管道之间才会出现风区
+        wind_chance: 20, // 每根新管道后面出现一个风区的概率（百分比）
+        wind_width: 8, // 风区的宽度（格）
+        wind_height: 24, // 风区的高度（格）
+        wind_strength: 0.5, // 风区每个物理步给玩家的竖直加速度，以当前重力为单位
+        wind_particle_ms: 120.0, // 风区里的粒子每隔这么久移动一格
+        toast_ms: 2500.0, // 成就解锁提示停留的时间
+        gap_start: 40, // 开局时管道缺口的高度
+        gap_min: 20, // 缺口缩到这么小就不再缩
+        gap_shrink_per_point: 0.5, // 每得一分缺口缩小多少格
+        gap_curve_points: 50, // 主菜单里的缺口曲线画到多少分
+        gap_curve_rows: 6, // 缺口曲线的高度（行）
+        gap_margin: 4, // 最小缺口至少要比玩家高这么多格，否则可能过不去
+        trail_min_speed: 0.55, // 管道速度超过这个值才开始拖影，普通难度的起始速度看不到
+        trail_full_speed: 1.0, // 达到这个速度时拖影最长最亮
+        trail_max_columns: 3, // 拖影最多画几列，越往后越暗
+        flap_buffer_ms: 100.0, // 冷却中按下的扇翅膀会保留这么久，冷却一结束就生效
+        fast_fall_factor: 1.75, // 按住快速下落键时，下落速度上限乘以这个倍数
+        gif_scale: 2, // 导出 GIF 时每格 2x2 像素，240x160 的画面
+        gif_max_frames: 40, // GIF 最多这么多帧，大约 3 秒
+        obstacle_pool_max: 16, // 对象池最多留这么多根管道，屏幕上同时只有三四根
+        assist_margin: 3, // 轻度辅助：下落中离地面或缺口下沿不到这么多格时自动扇一下
+        background_frame_ms: 400.0, // 多帧背景每帧停留的时间
+        speed_blend_ms: 330.0, // 速度平滑的时间常数，约 3 倍即一秒后基本追上目标
+        min_reaction_ms: 900.0, // 管道出现后至少过这么久才会到达玩家
+        obstacle_width: 1, // 管道占几列，1 到 4，宽一点更像原版
+        music_layer_score: 15.0, // 每多这么多分，游戏音乐多淡入一层伴奏
+        slowdown_factor: 0.75, // 管道附近减速：玩家和管道有重叠的列时时间按这个倍率流逝
+        slowdown_blend_ms: 120.0, // 减速和恢复的过渡时间常数
+        adaptive_window: 5, // 自适应难度参考最近几局
+        adaptive_short_ms: 10000.0, // 平均存活不到这么久就放松
+        adaptive_long_ms: 40000.0, // 平均存活超过这么久就收紧
+        adaptive_step: 0.25, // 每局最多调整的等级
+        adaptive_speed_range: 0.2, // 等级为 ±1 时速度浮动的比例
+        adaptive_gap_range: 8, // 等级为 ±1 时缺口高度增减的格数
+        flap_tap_fraction: 0.5, // 模拟扇翅膀：轻点一下只给这个比例的起飞速度
+        flap_hold_boost: 0.25, // 按住时每个物理步再加这个比例的起飞速度
+        flap_hold_cap: 1.25, // 按住加速最多到起飞速度的这个倍数
+        flap_hold_max_ms: 225.0, // 按住超过这么久就不再加速
+        photo_pan_step: 2.0, // 拍照模式每按一次方向键移动的格数
+        photo_max_zoom: 4.0, // 拍照模式最大放大倍数
+        world_top: 0, // 玩家能飞到的最高处，可以往下挪给 HUD 留出空间
+        world_bottom: 76, // 玩家底边超过这里就算落地，默认等于地面顶部（screen_height - ground_height）
+        ideal_path_pipes: 3, // 建议路线往前看几根管道
+        safe_zone_opacity: 0.25, // 缺口高亮往绿色混合的比例
+        flap_pitch_range: 0.12, // 扇翅膀音效的音高随扇之前的速度上下浮动的最大比例：下落越快越高，上升越快越低
+        warning_cells: 16, // 双缺口或合拢管道从生成到进入屏幕这么多格之前，右边缘一直闪警告
+        warning_blink_ms: 150.0, // 警告每隔这么久亮灭一次
+        spacing_jitter: 4, // 每根管道的间距在 obstacle_distance 上随机加减最多这么多格
+        checkpoint_pipes: 10, // 马拉松模式每穿过这么多根管道存一个检查点
+        checkpoint_retries: 3, // 马拉松模式一局里能回到检查点的次数
+        closing_min_score: 30, // 分数达到这么多后才会出现逐渐合拢的管道
+        closing_chance: 12, // 每根新管道是合拢管道的概率（百分比）
+        closing_extra: 12, // 合拢管道出现时的缺口比普通缺口大这么多格
+        closing_rate: 0.15, // 合拢管道每移动一格，缺口缩小这么多格，最小到玩家高度加 gap_margin
     };
 }
 
+impl DefaultParameters {
+    // 得分为 score 时普通管道的缺口高度
+    fn gap_size(&self, score: i32) -> i32 {
+        let shrink = (score as f32 * self.gap_shrink_per_point) as i32;
+        i32::max(self.gap_min, self.gap_start - shrink)
+    }
+
+    // 检查参数组合是否还能玩通，启动时调用
+    fn validate(&self) -> Result<(), String> {
+        let smallest = self.player_height + self.gap_margin;
+        if self.gap_min < smallest {
+            return Err(format!("gap_min ({}) must be at least player height + margin ({})", self.gap_min, smallest));
+        }
+        if self.double_gap_size < smallest {
+            return Err(format!("double_gap_size ({}) must be at least player height + margin ({})", self.double_gap_size, smallest));
+        }
+        if self.gap_start < self.gap_min {
+            return Err(format!("gap_start ({}) must not be smaller than gap_min ({})", self.gap_start, self.gap_min));
+        }
+        // 地面以下是实心的，world_bottom 可以比地面顶部高，但不能伸进地面里
+        let ground_top = self.screen_height - self.ground_height;
+        if self.world_top < 0 || self.world_top >= self.world_bottom || self.world_bottom > ground_top {
+            return Err(format!(
+                "world bounds ({}..{}) must satisfy 0 <= world_top < world_bottom <= ground top ({})",
+                self.world_top, self.world_bottom, ground_top,
+            ));
+        }
+        if self.world_bottom - self.world_top < self.gap_start {
+            return Err(format!(
+                "world bounds ({}..{}) must be at least gap_start ({}) tall",
+                self.world_top, self.world_bottom, self.gap_start,
+            ));
+        }
+        if self.gap_shrink_per_point < 0.0 {
+            return Err("gap_shrink_per_point must not be negative".to_string());
+        }
+        if !(1..=4).contains(&self.obstacle_width) {
+            return Err(format!("obstacle_width ({}) must be between 1 and 4", self.obstacle_width));
+        }
+        // obstacle_distance 最小是 40，间距再怎么抖也不能挤到一起
+        if !(0..=20).contains(&self.spacing_jitter) {
+            return Err(format!("spacing_jitter ({}) must be between 0 and 20", self.spacing_jitter));
+        }
+        if self.checkpoint_pipes < 1 {
+            return Err(format!("checkpoint_pipes ({}) must be at least 1", self.checkpoint_pipes));
+        }
+        if !(0.0..=0.5).contains(&self.flap_pitch_range) {
+            return Err(format!("flap_pitch_range ({}) must be between 0 and 0.5", self.flap_pitch_range));
+        }
+        if self.wind_height >= self.world_bottom - self.world_top {
+            return Err(format!(
+                "wind_height ({}) must be smaller than the flyable height ({})",
+                self.wind_height, self.world_bottom - self.world_top,
+            ));
+        }
+        if self.chaos_full_score <= self.chaos_start_score {
+            return Err(format!(
+                "chaos_full_score ({}) must be greater than chaos_start_score ({})",
+                self.chaos_full_score, self.chaos_start_score,
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.chaos_tint) || !(0.0..=1.0).contains(&self.chaos_detail) {
+            return Err(format!(
+                "chaos_tint ({}) and chaos_detail ({}) must be between 0 and 1",
+                self.chaos_tint, self.chaos_detail,
+            ));
+        }
+        if self.combo_fx_full <= self.combo_fx_threshold {
+            return Err(format!(
+                "combo_fx_full ({}) must be greater than combo_fx_threshold ({})",
+                self.combo_fx_full, self.combo_fx_threshold,
+            ));
+        }
+        Ok(())
+    }
+}
+
 struct State {
-    player: Player,
-    frame_time: f32,
+    run: RunState, // 只属于当前这一局的状态，开局时整体重建
     mode: GameMode,
-    score: i32,
-    obstacle_list: Vec<Obstacle>,
-    background_offset: f32,
-    distance: f32,
     menu_state: MenuState,
     settings: Settings,
     texture: Texture,
     high_score: i32, 
+    last_score: Option<i32>, // 最近一局的分数，保存在 last_score.txt；一局都没玩过时为 None
+    casual_board: Leaderboard,
+    hardcore_board: Leaderboard,
+    casual_time_board: Leaderboard,
+    hardcore_time_board: Leaderboard,
+    zen_board: Leaderboard,
+    leaderboard_view: LeaderboardKind,
+    audio: Audio,
+    menu_idle_ms: f32,
+    camera_offset_y: i32, // 世界坐标到屏幕坐标的竖直偏移，HUD 不受影响
+    next_seed: Option<u64>, // 指定下一局使用的种子（例如命令行 --seed）
+    tutorial_step: TutorialStep,
+    tutorial_timer: f32,
+    preview_obstacles: Vec<Obstacle>, // 障碍物菜单里的预览管道
+    preview_distance: f32,
+    preview_rng: RandomNumberGenerator, // 预览管道自己的随机数，不动本局的随机序列
+    hot_seat: Option<HotSeat>,
+    adaptive: AdaptiveDifficulty,
+    photo: Option<PhotoMode>,
+    storage: Storage,
+    share_code_input: TextInput,
+    share_code_error: Option<String>,
+    profiles: Vec<String>, // 存档菜单里列出的存档，打开菜单时刷新
+    profile_name_input: TextInput,
+    profile_error: Option<String>,
+    animation_clock_ms: f32, // 玩家动画时钟，每帧按实际经过的时间推进
+    total_coins: i32,
+    unlocked_skins: Vec<PlayerStyle>,
+    achievements: Vec<Achievement>, // 已解锁的成就
+    obstacle_pool: Vec<Obstacle>, // 移出屏幕的管道留着复用，生成新管道时不用再分配
+    background_frame: usize, // 背景动画当前帧，取帧时按帧数取余
+    background_frame_clock_ms: f32,
+}
+
+// 只属于一局的临时状态：分数、管道、计时、各种效果的剩余时间等；
+// 新字段加在这里并在 fresh_game 里给初值，重开时就不会带着上一局的残留
+struct RunState {
+    player: Player,
+    frame_time: f32,
+    score: i32,
+    obstacle_list: Vec<Obstacle>,
+    distance: f32,
+    spacing_offset: i32, // 下一根管道的间距相对 obstacle_distance 的随机偏移
+    flags: RunFlags,
+    lives: i32,
+    invuln_remaining: f32, // 剩余无敌时间（毫秒）
+    invuln_duration: f32, // 这一次无敌的总时长，用来画倒计时条
+    end_message: Option<String>, // 结束界面上的提示，例如截图保存结果
+    flap_cooldown_remaining: f32,
+    seed: u64, // 本局障碍物随机数种子，用于重玩同一关
+    rng: RandomNumberGenerator,
+    rewind_buffer: VecDeque<Snapshot>,
+    input_log: VecDeque<InputLogEntry>, // 只在调试模式下记录，保留最近 input_log_ms 毫秒
+    high_score_before_run: i32,
+    recorded_on: Option<LeaderboardKind>, // 本局成绩记到了哪个排行榜，倒带时要撤销
+    unsubmitted: bool, // 已经结束但成绩还没提交到服务器；倒带复活后清掉，等离开结束界面才提交
+    clean_passes: i32, // 连击模式下连续没有擦边地穿过的管道数
+    pipes_passed: i32, // 穿过的管道数，不含各种加分
+    coin_list: Vec<Coin>,
+    wind_zones: Vec<WindZone>,
+    coins: i32, // 本局收集的金币，结束时才加到总数里
+    toasts: VecDeque<String>, // 排队等待显示的提示，一次显示一条
+    toast_remaining_ms: f32,
+    time_ms: f32, // 本局存活时间
+    buffered_flap_until: Option<f32>, // 还没生效的扇翅膀输入，到这个时刻（按 time_ms 计）为止有效
+    fast_falling: bool, // 本帧是否按住了快速下落键
+    flap_held: bool, // 本帧是否按住了扇翅膀键
+    flap_hold_remaining_ms: f32, // 模拟扇翅膀还能继续加速的时间，松开键就清零
+    current_speed: f32, // 管道当前的移动速度，平滑地追随 obstacle_speed()
+    time_scale: f32, // 时间流逝的倍率，管道附近减速时小于 1
+    combo_glow: f32, // 连击特效的强度 0~1
+    checkpoint: Option<Snapshot>, // 马拉松模式最近的检查点
+    checkpoint_retries: i32, // 马拉松模式还能回到检查点的次数
+    end_confirm_quit: bool, // 结束界面按了 Q，等待确认
+    background_offset: f32,
 }
 
 struct MenuState {
     current_menu: MainMenuOption,
     selected_option: i32,
-    in_submenu: bool,
+    parent_option: i32, // 当前子菜单在主菜单里的位置，返回时光标停在这里
+    confirm_quit: bool, // 选了退出，等待再次确认
+    confirm_reset: bool, // 选了重置进度，等待确认
+    confirm_delete: bool, // 存档菜单里按了 D，等待确认删除选中的存档
 }
 
 #[derive(PartialEq, Eq)]
@@ -74,514 +705,4946 @@ enum MainMenuOption {
     Background,
     Player,
     Obstacle,
+    Leaderboard,
+    Options,
+    ShareCode,
+    Achievements,
+    Profiles,
+    NewProfile,
+}
+
+// 成就：一旦达成就永久解锁，存到文件里
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Achievement {
+    FirstPipe,
+    Pipes10,
+    Pipes50,
+    Score50,
+    Perfect5,
+    Coins10,
+    HardPipes20,
+}
+
+// 分享码：把种子和影响关卡的设置打包成一串字母数字，别人输入后能玩到完全相同的一局
+struct ShareCode {
+    seed: u64,
+    difficulty: Difficulty,
+    run_mode: RunMode,
+    obstacle_distance: i32,
+    adaptive_level: Option<f32>, // 自适应难度的等级，固定难度和旧的分享码没有
+    spacing_jitter: bool,
 }
 
 struct Settings {
     background_style: BackgroundStyle,
     player_style: PlayerStyle,
     obstacle_distance: i32,
+    difficulty: Difficulty,
+    run_mode: RunMode,
+    music_volume: i32, // 0~10
+    sound_volume: i32, // 音效音量 0~10
+    starting_lives: i32, // 1 为经典玩法
+    flap_cooldown_ms: i32, // 两次扇翅膀之间的最短间隔，0 为不限制
+    debug: bool, // 调试模式，游戏中按 F3 切换
+    theme: Theme,
+    start_grace_ms: Option<i32>, // 开局无敌时间，None 表示按难度自动
+    scroll_direction: ScrollDirection,
+    gravity_curve: GravityCurve,
+    ghost_obstacles: bool, // 在屏幕右边缘提示还没出现的管道
+    player_name: String,
+    submit_url: Option<String>, // 设置后每局结束把成绩 POST 到这个地址，默认关闭
+    animation_fps: f32, // 玩家动画每秒播放的帧数，和物理帧无关
+    speed_trails: bool, // 管道高速移动时在后面画拖影
+    reduced_motion: bool, // 关掉拖影并停止背景滚动
+    scoring: Scoring,
+    keys: KeyBindings,
+    flap_assist: FlapAssist,
+    near_pipe_slowdown: bool, // 穿过管道时稍微放慢时间，默认关闭
+    hud_panel_opacity: i32, // HUD 背后底条的不透明度，0 到 10，0 表示不画
+    classic_flap: bool, // 经典扇翅膀：按下瞬间给满起飞速度；关掉后按得越久飞得越高
+    smooth_sprites: bool, // 玩家贴图缩放时做插值，默认按像素画取最近的像素
+    ideal_path: bool, // 练习用：画出穿过接下来几个缺口的建议路线
+    language: Language,
+    spacing_jitter: bool, // 管道间距随机浮动，关掉后严格按 obstacle_distance 出现
+    background_brightness: i32, // 背景亮度 3~10，表示 0.3~1.0，保存在 brightness.txt
+    dim_sprites: bool, // 玩家贴图也按背景亮度调暗
+    safe_zone: bool, // 新手辅助：把前方最近一根管道的缺口涂成淡绿色
+    foreground: bool, // 有前景贴图时把它画在玩家和管道前面
+}
+
+// 文本输入框：把按键转成字符，处理退格和长度上限，渲染时在末尾画光标
+struct TextInput {
+    text: String,
+    max_len: usize,
+    charset: Charset,
+}
+
+// 输入框接受的字符
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Charset {
+    Code, // 只有大写字母和数字，分享码用，不管是否按着 Shift
+    Name, // 字母、数字和空格，按住 Shift 输入大写
+}
+
+// 游戏中的按键，可以在设置菜单里改；菜单里的按键固定不变
+struct KeyBindings {
+    flap: VirtualKeyCode,
+    fast_fall: VirtualKeyCode, // 按住时提高下落速度上限
+    restart: Option<VirtualKeyCode>, // 快速重开：游戏中或结束界面按下直接开新一局；默认不绑定，免得误触丢掉一局
+}
+
+// 外观主题
+struct Theme {
+    pipe_glyphs: PipeGlyphs,
+    menu_highlight: ThemeColor, // 菜单中选中项的颜色
+    menu_text: ThemeColor, // 菜单中其余选项的颜色
+    gap_feather: bool, // 紧挨缺口的一格管道用暗一些的颜色，让缺口的边缘更显眼
+    passed_pipe: Option<ThemeColor>, // 已经穿过的管道改画成这个颜色（调暗），None 表示不变
+}
+
+// 主题里可选的颜色
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ThemeColor {
+    Yellow,
+    White,
+    Cyan,
+    Magenta,
+    Orange,
+    Green,
+    Gray,
+}
+
+// 管道使用的字符组合：管身和靠近缺口的管口
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum PipeGlyphs {
+    Classic,
+    Solid,
+    Shade,
+    Hash,
+    Brick, // 管身带砖墙花纹，花纹跟着管道一起移动
+}
+
+// 开局时记录的本局标记
+#[derive(Clone, Copy)]
+struct RunFlags {
+    difficulty: Difficulty,
+    mode: RunMode,
+    assists_used: bool,
+    practice: bool,
+    scoring: Scoring,
+    adaptive_level: f32, // 开局时自适应难度的等级，固定难度恒为 0
+    fixed_level: bool, // 沿用了指定的自适应等级（同种子重来、双人模式、分享码），这局不计入自适应统计
+}
+
+struct Leaderboard {
+    kind: LeaderboardKind,
+    entries: Vec<LeaderboardEntry>,
+}
+
+// 得分包含擦边奖励等加分，pipes 是实际穿过的管道数
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct LeaderboardEntry {
+    score: i32,
+    pipes: i32,
+    time_ms: i32, // 存活时间
+    difficulty: Option<Difficulty>, // 旧版文件没有记录难度
+}
+
+// 每种玩家样式的手感：重力、最大下落速度、扇翅膀的初速度
+struct PlayerPhysics {
+    gravity: f32,
+    max_fall_speed: f32,
+    flap_velocity: f32,
+    curve: GravityCurve,
+}
+
+// 下落加速度的变化方式
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum GravityCurve {
+    Linear, // 经典手感：加速度恒定，整体偏飘
+    Quadratic, // 加速度随下落速度增大：顶点附近悬停得更久，下落后段越来越沉
+    Snappy, // 上升时照常减速，过了顶点后加速度加倍，落得又快又干脆
 }
 
+#[derive(Clone, Debug, PartialEq)]
 struct Player {
     x: i32,
     y: i32,
     velocity: f32,
 }
 
+#[derive(Clone, Debug, PartialEq)]
 struct Obstacle {
     x: f32,
     gap_y: i32,
+    second_gap_y: Option<i32>, // 双缺口管道的第二个缺口中心，和第一个缺口一样大
     size: i32,
     scored: bool, // 是否已得分
+    near_miss: bool, // 本帧玩家是否擦边，用于高亮
+    near_miss_awarded: bool, // 擦边奖励每根管道只给一次
+    telegraph: bool, // 因为速度太快而生成在屏幕外更远处，进屏幕前就在右边缘提示
+    width: i32, // 占几列，从 x 往右数
+    closing: Option<f32>, // 合拢管道当前的缺口高度（带小数），随着管道靠近逐渐缩小；普通管道为 None
+}
+
+// 金币：放在管道缺口中间，碰到就收下，一局结束后计入总数
+#[derive(Clone, Debug, PartialEq)]
+struct Coin {
+    x: f32,
+    y: i32,
+}
+
+// 风区：管道之间的一块区域，玩家在里面时每个物理步多受一个竖直的力
+#[derive(Clone)]
+struct WindZone {
+    x: f32,
+    top: i32,
+    bottom: i32, // 不含
+    force: f32, // 以重力为单位，负数是上升气流，正数是下沉气流
+}
+
+// 世界坐标到屏幕区域的映射：先减去镜头偏移，再缩放并平移到区域内，区域外的部分不绘制
+struct Viewport {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+    scale_x: f32,
+    scale_y: f32,
+    camera_offset_y: i32,
+}
+
+// 调试模式下逐帧记录的输入和物理状态，死亡时写到文件里
+#[derive(Clone, Copy)]
+struct InputLogEntry {
+    time_ms: f32,
+    frame_time_ms: f32,
+    flap: bool, // 这一帧是否按了扇翅膀
+    player_y: i32,
+    velocity: f32,
+    nearest_gap: Option<(i32, i32)>, // 前方最近的管道上离玩家最近的缺口
+}
+
+// 高分时画面的紧张感，由 chaos_level 统一换算成各项渲染参数
+#[derive(Clone, Copy)]
+struct ChaosEffects {
+    scroll_factor: f32, // 背景滚动速度的倍数
+    tint: f32, // 背景往红色混合的比例
+    detail: f32, // 背景上出现光点的格子比例
+}
+
+// 倒带用的局面快照
+#[derive(Clone)]
+struct Snapshot {
+    player: Player,
+    obstacle_list: Vec<Obstacle>,
+    score: i32,
+    distance: f32,
+    spacing_offset: i32,
+    background_offset: f32,
+    rng: RandomNumberGenerator,
+    clean_passes: i32,
+    pipes_passed: i32,
+    coin_list: Vec<Coin>,
+    wind_zones: Vec<WindZone>,
+    run_coins: i32,
+    run_time_ms: f32,
+}
+
+// 命令行启动参数
+struct LaunchOptions {
+    play: bool,
+    player_style: Option<PlayerStyle>,
+    background_style: Option<BackgroundStyle>,
+    seed: Option<u64>,
+    letterbox: bool, // 窗口变大时游戏区保持原尺寸居中
+    portrait: bool, // 以竖长窗口启动，游戏区等比缩小后居中
+    no_save: bool,
+    player_name: Option<String>,
+    submit_url: Option<String>,
+    profile: Option<String>,
+}
+
+// 所有存档文件的读写都经过这里；关闭时（--no-save）数据只保存在本次运行的内存里。
+// 每个存档的文件放在 profiles/<名字>/ 下，default 存档直接用当前目录，以前的存档照样能读到
+#[derive(Clone)]
+struct Storage {
+    enabled: bool,
+    profile: String,
 }
 
 struct Texture {
-    player_dragon: image::DynamicImage,
-    player_bird: image::DynamicImage,
-    player_duck: image::DynamicImage,
-    background_stars: image::DynamicImage,
-    background_clouds: image::DynamicImage,
-    background_mountains: image::DynamicImage,
+    // 每种玩家的动画帧，目前每种只有一帧
+    player_dragon: Vec<image::DynamicImage>,
+    player_bird: Vec<image::DynamicImage>,
+    player_duck: Vec<image::DynamicImage>,
+    // 每种背景的动画帧，只有一帧的背景就只滚动不变化
+    background_stars: Vec<image::DynamicImage>,
+    background_clouds: Vec<image::DynamicImage>,
+    background_mountains: Vec<image::DynamicImage>,
     menu_title: Vec<(i32, i32, FontCharType)>,
+    foreground: Option<image::DynamicImage>, // 贴在屏幕底部、画在玩家前面的前景层，带透明通道
+}
+
+// 背景音乐曲目
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum MusicTrack {
+    Menu,
+    Gameplay,
+}
+
+struct Audio {
+    _stream: Option<OutputStream>, // 必须一直持有，否则声音会被立即停止
+    handle: Option<OutputStreamHandle>,
+    music: Option<Sink>,
+    layers: Vec<Sink>, // 和主曲目同步循环的强度层，音量随分数淡入
+    current_track: Option<MusicTrack>,
+    flap_sound: Option<Buffered<Decoder<BufReader<File>>>>, // 启动时解码好，每次扇翅膀复制一份播放
 }
 
 impl State {
-    fn new() -> Self {
-        let texture = Texture::new();
+    // 资源加载失败时返回出错的资源名，由 main 打印后退出
+    fn new(storage: Storage) -> Result<Self, String> {
+        let texture = Texture::new()?;
 
-        let high_score = match fs::read_to_string("highscore.txt") {
-        Ok(content) => content.trim().parse::<i32>().unwrap_or(0),
-        Err(_) => 0,
-    };
-        Self {
-            player: Player::new(2, 25),
-            frame_time: 0.0,
+        let settings = Settings {
+            background_style: BackgroundStyle::Mountains,
+            player_style: PlayerStyle::Duck,
+            obstacle_distance: 50,
+            difficulty: Difficulty::Normal,
+            run_mode: RunMode::Classic,
+            music_volume: 7,
+            sound_volume: 7,
+            starting_lives: 1,
+            flap_cooldown_ms: 0,
+            debug: false,
+            theme: Theme {
+                pipe_glyphs: PipeGlyphs::Classic,
+                menu_highlight: ThemeColor::Yellow,
+                menu_text: ThemeColor::White,
+                gap_feather: false,
+                passed_pipe: None,
+            },
+            start_grace_ms: None,
+            scroll_direction: ScrollDirection::RightToLeft,
+            gravity_curve: GravityCurve::Linear,
+            ghost_obstacles: false,
+            player_name: "Player".to_string(),
+            submit_url: None,
+            animation_fps: 8.0,
+            speed_trails: true,
+            reduced_motion: false,
+            scoring: Scoring::Points,
+            keys: KeyBindings {
+                flap: VirtualKeyCode::Space,
+                fast_fall: VirtualKeyCode::Down,
+                restart: None,
+            },
+            flap_assist: FlapAssist::Off,
+            near_pipe_slowdown: false,
+            hud_panel_opacity: 6,
+            classic_flap: true,
+            smooth_sprites: false,
+            ideal_path: false,
+            language: Language::English,
+            spacing_jitter: true,
+            background_brightness: 10,
+            dim_sprites: false,
+            safe_zone: false,
+            foreground: true,
+        };
+        // 存档里的数据由 load_profile 读入
+        let mut state = Self {
             mode: GameMode::Menu,
-            score: 0,
-            obstacle_list: vec![Obstacle::new(DEFAULT_PARAMETERS.screen_width, 0)],
-            background_offset: 0.0,
-            distance: 0.0,
             menu_state: MenuState {
                 current_menu: MainMenuOption::Main,
                 selected_option: 0,
-                in_submenu: false,
-            },
-            settings: Settings {
-                background_style: BackgroundStyle::Mountains,
-                player_style: PlayerStyle::Duck,
-                obstacle_distance: 50,
+                parent_option: 0,
+                confirm_quit: false,
+                confirm_reset: false,
+                confirm_delete: false,
             },
+            run: RunState::fresh_game(&settings, RandomNumberGenerator::new().next_u64(), 0, 0.0),
+            settings,
             texture,
-            high_score,
-        }
+            high_score: 0,
+            last_score: None,
+            casual_board: Leaderboard::empty(LeaderboardKind::Casual),
+            hardcore_board: Leaderboard::empty(LeaderboardKind::Hardcore),
+            casual_time_board: Leaderboard::empty(LeaderboardKind::CasualTime),
+            hardcore_time_board: Leaderboard::empty(LeaderboardKind::HardcoreTime),
+            zen_board: Leaderboard::empty(LeaderboardKind::Zen),
+            leaderboard_view: LeaderboardKind::Casual,
+            audio: Audio::new(),
+            menu_idle_ms: 0.0,
+            camera_offset_y: 0,
+            next_seed: None,
+            tutorial_step: TutorialStep::Flap,
+            tutorial_timer: 0.0,
+            preview_obstacles: Vec::new(),
+            preview_distance: 0.0,
+            preview_rng: RandomNumberGenerator::new(),
+            hot_seat: None,
+            photo: None,
+            adaptive: AdaptiveDifficulty {
+                recent_runs: VecDeque::new(),
+                level: 0.0,
+                pending: false,
+            },
+            storage,
+            share_code_input: TextInput::new(ShareCode::LEN, Charset::Code),
+            share_code_error: None,
+            profiles: Vec::new(),
+            profile_name_input: TextInput::new(16, Charset::Name),
+            profile_error: None,
+            animation_clock_ms: 0.0,
+            total_coins: 0,
+            unlocked_skins: Vec::new(),
+            achievements: Vec::new(),
+            obstacle_pool: Vec::new(),
+            background_frame: 0,
+            background_frame_clock_ms: 0.0,
+        };
+        state.load_profile();
+        Ok(state)
     }
 
-    fn update_background(&mut self, ctx: &mut BTerm) {
-        self.background_offset += DEFAULT_PARAMETERS.background_speed * ctx.frame_time_ms;
-        if self.background_offset > DEFAULT_PARAMETERS.screen_width as f32 {
-            self.background_offset -= DEFAULT_PARAMETERS.screen_width as f32;
+    // 从当前存档读入最高分、金币、皮肤、成就、排行榜和背景亮度；切换存档后整体重读
+    fn load_profile(&mut self) {
+        let storage = self.storage.clone();
+        self.high_score = match storage.read("highscore.txt") {
+            Some(content) => content.trim().parse::<i32>().unwrap_or(0),
+            None => 0,
+        };
+        self.run.high_score_before_run = self.high_score;
+        self.last_score = storage.read(LAST_SCORE_FILE).and_then(|content| content.trim().parse::<i32>().ok());
+        self.total_coins = match storage.read(COINS_FILE) {
+            Some(content) => content.trim().parse::<i32>().unwrap_or(0),
+            None => 0,
+        };
+        // 免费的皮肤总是可用，其余的要用金币解锁。
+        // 加入金币之前所有皮肤都是免费的：有旧存档却还没有 skins.txt 的玩家，把当时能用的皮肤全部算作已解锁。
+        // 第一次读入后马上写出 skins.txt，以后就不会再被当成旧存档
+        self.unlocked_skins = match storage.read(SKINS_FILE) {
+            Some(content) => content.lines().filter_map(PlayerStyle::from_name).collect(),
+            None if Self::has_legacy_save(&storage) => PlayerStyle::ALL.to_vec(),
+            None => Vec::new(),
+        };
+        for style in PlayerStyle::ALL {
+            if style.unlock_cost() == 0 && !self.unlocked_skins.contains(&style) {
+                self.unlocked_skins.push(style);
+            }
         }
-    }
-
-    fn playing(&mut self, ctx: &mut BTerm) {
-        self.update_background(ctx);
-        self.render_background(ctx); // 先渲染背景
-        
-        self.frame_time += ctx.frame_time_ms;
-
-        if self.frame_time > DEFAULT_PARAMETERS.frame_duration {
-            self.player.gravity_to_move();
-            self.frame_time = 0.0;
+        if !storage.exists(SKINS_FILE) {
+            self.save_unlocked_skins();
         }
-
-        // 按下空格键时飞起
-        if let Some(VirtualKeyCode::Space) = ctx.key {
-            self.player.flap();
+        if !self.unlocked_skins.contains(&self.settings.player_style) {
+            self.settings.player_style = PlayerStyle::Duck;
         }
+        self.achievements = match storage.read(ACHIEVEMENTS_FILE) {
+            Some(content) => content.lines().filter_map(Achievement::from_id).collect(),
+            None => Vec::new(),
+        };
+        for kind in LeaderboardKind::ALL {
+            *self.leaderboard_mut(kind) = Leaderboard::load(kind, &storage);
+        }
+        self.settings.background_brightness = match storage.read(BRIGHTNESS_FILE) {
+            Some(content) => content.trim().parse::<i32>().unwrap_or(10).clamp(3, 10),
+            None => 10,
+        };
+    }
 
-        // 渲染玩家
-        self.player.render(ctx, &self.texture, &self.settings.player_style);
+    // 皮肤要花金币解锁之前留下的存档：有最高分或任何一个排行榜文件
+    fn has_legacy_save(storage: &Storage) -> bool {
+        storage.exists("highscore.txt") || LeaderboardKind::ALL.iter().any(|kind| storage.exists(kind.file_name()))
+    }
 
-        // 显示分数和提示
-        ctx.print(0, 0, "Press Space to flap");
-        ctx.print(0, 1, &format!("Score: {}", self.score));
+    fn save_unlocked_skins(&self) {
+        let names: Vec<&str> = self.unlocked_skins.iter().map(|s| s.name()).collect();
+        self.storage.write(SKINS_FILE, &names.join("\n"));
+    }
 
-        // 渲染障碍物
-        for obstacle in &mut self.obstacle_list {
-            obstacle.render(ctx);
+    // 换到另一个存档，这个存档下的数据全部重新读入
+    fn switch_profile(&mut self, name: &str) {
+        self.storage = Storage::new(self.storage.enabled, name);
+        self.load_profile();
+    }
 
-            if self.player.x > obstacle.x as i32 && !obstacle.scored {
-                self.score += 1;
-                obstacle.scored = true; // 标记已得分
-            }
+    // 按扇之前的速度决定音高：下落到最大速度时最高，上升时偏低，变化幅度由 flap_pitch_range 控制
+    fn play_flap_sound(&self) {
+        let physics = self.physics();
+        let speed = (self.run.player.velocity / physics.max_fall_speed).clamp(-1.0, 1.0);
+        let rate = 1.0 + speed * DEFAULT_PARAMETERS.flap_pitch_range;
+        self.audio.play_flap(rate, self.settings.sound_volume as f32 / 10.0);
+    }
 
-            if obstacle.hit_obstacle(&self.player) {
-                self.mode = GameMode::End;
+    // 根据当前模式切换背景音乐：菜单和游戏各一首，死亡界面静音
+    fn update_music(&mut self) {
+        let track = match self.mode {
+            GameMode::Menu | GameMode::Demo => Some(MusicTrack::Menu),
+            GameMode::Playing | GameMode::Tutorial | GameMode::Photo => Some(MusicTrack::Gameplay),
+            GameMode::End | GameMode::HotSeat => None,
+        };
+        let volume = self.settings.music_volume as f32 / 10.0;
+        match track {
+            Some(track) => {
+                self.audio.play_music(track, volume);
+                self.audio.set_intensity(self.music_intensity(), volume);
             }
+            None => self.audio.stop_music(),
         }
-        
-        self.obstacle_list.retain(|obstacle| obstacle.x > 0.0);
-        self.distance += DEFAULT_PARAMETERS.obstacle_speed;
+    }
 
-        if(self.distance > self.settings.obstacle_distance as f32){
-            self.obstacle_list.push(Obstacle::new(
-                DEFAULT_PARAMETERS.screen_width,
-                self.score
-            ));
-            self.distance = 0.0;
+    // 音乐强度：按难度曲线用的分数计算，第 n 层在 n-1 到 n 之间逐渐淡入；减少动态效果时保持平静
+    fn music_intensity(&self) -> f32 {
+        if self.settings.reduced_motion || !matches!(self.mode, GameMode::Playing | GameMode::Photo) {
+            return 0.0;
         }
+        self.curve_score() as f32 / DEFAULT_PARAMETERS.music_layer_score
+    }
 
-        // 判断是否碰到边界或障碍物
-        if self.player.y + DEFAULT_PARAMETERS.player_height > DEFAULT_PARAMETERS.screen_height   {
-            self.mode = GameMode::End;
-        }
+    // 新管道出现的位置：通常在屏幕右边缘，速度很快时放到更远的屏幕外，
+    // 保证管道从出现到碰到玩家至少有 min_reaction_ms 的时间。
+    // 速度按平滑后的帧率换算，单帧卡顿不会让位置忽远忽近；
+    // 也不会比上一根管道更靠左，列表保持从左到右的顺序，移出屏幕的管道才能从最前面回收
+    fn spawn_x(&self, ctx: &BTerm, speed: f32) -> i32 {
+        let cells_per_ms = speed * smoothed_fps(ctx) / 1000.0;
+        let lead = (DEFAULT_PARAMETERS.min_reaction_ms * cells_per_ms).ceil() as i32;
+        let previous = self.run.obstacle_list.last().map_or(i32::MIN, |obstacle| obstacle.right());
+        DEFAULT_PARAMETERS.screen_width
+            .max(self.run.player.x + DEFAULT_PARAMETERS.player_width + lead)
+            .max(previous)
     }
 
-    fn restart(&mut self) {
-        
-        self.player = Player::new(2, 25);
-        self.frame_time = 0.0;
-        self.mode = GameMode::Playing;
-        self.score = 0;
-        self.distance = 0.0;
-        self.obstacle_list = vec![Obstacle::new(
-            DEFAULT_PARAMETERS.screen_width,
-            self.score
-        )];
-        self.background_offset = 0.0;
+    // 下一根管道的间距偏移，加减对称所以平均间距不变；
+    // 关掉浮动时也照样抽一次随机数，同一个种子生成的缺口序列不受这个设置影响
+    fn roll_spacing_offset(&mut self) -> i32 {
+        let jitter = DEFAULT_PARAMETERS.spacing_jitter;
+        let offset = self.run.rng.range(-jitter, jitter + 1);
+        if self.settings.spacing_jitter { offset } else { 0 }
     }
 
-    fn end(&mut self, ctx: &mut BTerm) {
-        // 更新历史最高分（如果当前分数更高）
-        if self.score > self.high_score {
-            self.high_score = self.score;
-            let _ = fs::write("highscore.txt", self.high_score.to_string()); // 保存到文件
-        }
-        self.update_background(ctx);
-        self.render_background(ctx); // 渲染背景
+    // 目标速度：由难度和连击决定
+    fn obstacle_speed(&self) -> f32 {
+        DEFAULT_PARAMETERS.obstacle_speed * self.run.flags.speed_factor() * self.combo_speed_factor()
+    }
 
-        ctx.print_color_centered(5, WHITE,BLACK,"You are dead!");
-        ctx.print_color_centered(6, WHITE,BLACK,&format!("Final Score: {}", self.score));
-        ctx.print_color_centered(7,WHITE,BLACK, &format!("High Score: {}", self.high_score)); // 显示历史最高分
-        ctx.print_color_centered(8,WHITE,BLACK, "(P) Play Again");
-        ctx.print_color_centered(9, WHITE,BLACK,"(M) Main Menu");
-        ctx.print_color_centered(10, WHITE,BLACK,"(Q) Quit Game");
+    // 实际速度每帧向目标速度靠拢，大约一秒追上，速度变化时（例如连击清零）不会突然跳变
+    fn update_current_speed(&mut self, ctx: &BTerm) -> f32 {
+        let blend = f32::min(1.0, frame_time_ms(ctx) / DEFAULT_PARAMETERS.speed_blend_ms);
+        self.run.current_speed += (self.obstacle_speed() - self.run.current_speed) * blend;
+        self.run.current_speed
+    }
 
-        // 判断按键
-        if let Some(key) = ctx.key {
-            match key {
-                VirtualKeyCode::P => self.restart(),
-                VirtualKeyCode::M => self.mode = GameMode::Menu,
-                VirtualKeyCode::Q => ctx.quitting = true,
-                _ => {}
-            }
-        }
+    // 只有玩家自己操作的正式局才用模拟扇翅膀，演示和教程保持经典手感
+    fn analog_flap(&self) -> bool {
+        !self.settings.classic_flap && matches!(self.mode, GameMode::Playing)
     }
 
-    fn main_menu(&mut self, ctx: &mut BTerm) {
-        self.render_background(ctx); // 渲染背景
-        self.update_background(ctx);
-        // 渲染标题
-        for (x, y, char) in &self.texture.menu_title {
-            ctx.set(*x, *y, YELLOW, RGBA::from_u8(0,0,0,0), *char);
+    // 本局的手感参数，已按本局难度的物理步长换算；按住快速下落时下落速度上限更高
+    fn physics(&self) -> PlayerPhysics {
+        let mut physics = self.settings.physics().rescaled(self.run.flags.difficulty.frame_duration());
+        if self.run.fast_falling {
+            physics.max_fall_speed *= DEFAULT_PARAMETERS.fast_fall_factor;
         }
+        physics
+    }
 
-        // 根据当前菜单状态渲染不同的菜单
-        match self.menu_state.current_menu {
-            MainMenuOption::Main => self.render_main_menu(ctx),
-            MainMenuOption::Background => self.render_background_menu(ctx),
-            MainMenuOption::Player => self.render_player_menu(ctx),
-            MainMenuOption::Obstacle => self.render_obstacle_menu(ctx),
-        }
+    // 背景从左往右滚时，看上去是玩家在往左飞，贴图左右翻转成朝左
+    fn player_mirrored(&self) -> bool {
+        self.settings.scroll_direction == ScrollDirection::LeftToRight
+    }
 
-        // 处理菜单导航
-        self.handle_menu_input(ctx);
+    fn player_sprite(&self) -> &image::DynamicImage {
+        self.texture.player_image(&self.settings.player_style, self.animation_frame())
     }
 
-    fn render_main_menu(&mut self, ctx: &mut BTerm) {
-        let options = vec![
-            "Start Game",
-            "Background Style",
-            "Player Style",
-            "Obstacle Distance",
-            "Quit Game",
-        ];
+    // 当前应显示的玩家动画帧序号
+    fn animation_frame(&self) -> usize {
+        (self.animation_clock_ms / 1000.0 * self.settings.animation_fps) as usize
+    }
 
-        for (i, option) in options.iter().enumerate() {
-            let color = if i as i32 == self.menu_state.selected_option {
-                YELLOW
-            } else {
-                WHITE
-            };
+    // 连击模式的额外速度倍率，经典模式恒为 1
+    fn combo_speed_factor(&self) -> f32 {
+        if self.run.flags.mode != RunMode::Combo {
+            return 1.0;
+        }
+        f32::min(
+            1.0 + self.run.clean_passes as f32 * DEFAULT_PARAMETERS.combo_speed_step,
+            DEFAULT_PARAMETERS.combo_max_speed,
+        )
+    }
 
-            ctx.print_color_centered(15 + i * 2, color, RGBA::from_u8(0,0,0,0), option);
+    // 把这一帧新达成的成就记下来并排队提示
+    fn check_achievements(&mut self) {
+        let unlocked: Vec<Achievement> = Achievement::ALL
+            .iter()
+            .copied()
+            .filter(|achievement| !self.achievements.contains(achievement) && achievement.is_met(self))
+            .collect();
+        if unlocked.is_empty() {
+            return;
         }
+        for achievement in unlocked {
+            self.achievements.push(achievement);
+            self.run.toasts.push_back(fill(self.tr(Text::AchievementUnlocked), &[&self.tr(achievement.name())]));
+        }
+        let ids: Vec<&str> = self.achievements.iter().map(|a| a.id()).collect();
+        self.storage.write(ACHIEVEMENTS_FILE, &ids.join("\n"));
     }
 
-    fn render_background_menu(&mut self, ctx: &mut BTerm) {
-        let options = vec![
-            "Stars",
-            "Clouds",
-            "Mountains",
-            "Back",
-        ];
+    // 按当前语言取界面文字
+    fn tr(&self, key: Text) -> &'static str {
+        self.settings.language.text(key)
+    }
 
-        ctx.print_color_centered(12, WHITE,BLACK,"Select Background Style");
+    // 提示文字里显示的按键名，方向键和修饰键按当前语言翻译
+    fn key_name(&self, key: VirtualKeyCode) -> &'static str {
+        match key {
+            VirtualKeyCode::Space => self.tr(Text::KeySpace),
+            VirtualKeyCode::Up => self.tr(Text::KeyUp),
+            VirtualKeyCode::Down => self.tr(Text::KeyDown),
+            VirtualKeyCode::LShift => self.tr(Text::KeyShift),
+            VirtualKeyCode::LControl => self.tr(Text::KeyCtrl),
+            VirtualKeyCode::W => "W",
+            VirtualKeyCode::Z => "Z",
+            VirtualKeyCode::X => "X",
+            VirtualKeyCode::J => "J",
+            VirtualKeyCode::K => "K",
+            VirtualKeyCode::R => "R",
+            _ => "?",
+        }
+    }
 
-        for (i, option) in options.iter().enumerate() {
-            let color = if i as i32 == self.menu_state.selected_option {
-                YELLOW
-            } else {
-                WHITE
-            };
+    // 屏幕上方居中显示队首的提示，时间到了换下一条
+    fn render_toast(&mut self, ctx: &mut BTerm) {
+        let Some(message) = self.run.toasts.front() else {
+            return;
+        };
+        if self.run.toast_remaining_ms <= 0.0 {
+            self.run.toast_remaining_ms = DEFAULT_PARAMETERS.toast_ms;
+        }
+        ctx.print_color_centered_at(center_x(), 6, YELLOW, BLACK, message);
+        self.run.toast_remaining_ms -= frame_time_ms(ctx);
+        if self.run.toast_remaining_ms <= 0.0 {
+            self.run.toasts.pop_front();
+        }
+    }
 
-            let is_active = match (i, &self.settings.background_style) {
-                (0, BackgroundStyle::Stars) => "(*) ",
-                (1, BackgroundStyle::Clouds) => "(*) ",
-                (2, BackgroundStyle::Mountains) => "(*) ",
-                _ => "( ) ",
-            };
+    // 拖影强度 0~1，随管道速度增大，连击特效亮起时至少和特效一样强；关掉拖影或开了减少动态效果时为 0
+    fn trail_intensity(&self) -> f32 {
+        if !self.settings.speed_trails || self.settings.reduced_motion {
+            return 0.0;
+        }
+        let progress = (self.run.current_speed - DEFAULT_PARAMETERS.trail_min_speed)
+            / (DEFAULT_PARAMETERS.trail_full_speed - DEFAULT_PARAMETERS.trail_min_speed);
+        f32::max(progress.clamp(0.0, 1.0), self.run.combo_glow)
+    }
+
+    // 游戏中的全部 HUD：提示、分数和最高分、生命、无敌倒计时、双人模式的当前玩家、连击速度条、马拉松的检查点
+    fn render_hud(&self, ctx: &mut BTerm) {
+        let rows = if self.run.flags.mode == RunMode::Sandbox {
+            2
+        } else if matches!(self.run.flags.mode, RunMode::Combo | RunMode::Marathon) {
+            5
+        } else if self.hot_seat.is_some() {
+            4
+        } else {
+            3
+        };
+        self.render_hud_panel(ctx, rows);
+
+        let flap_hint = fill(self.tr(Text::PressToFlap), &[&self.key_name(self.settings.keys.flap)]);
+        ctx.print(0, 0, &flap_hint);
+        if self.photo_mode_allowed() {
+            ctx.print(DEFAULT_PARAMETERS.screen_width - 16, 0, self.tr(Text::PhotoModeHint));
+        }
+        if self.run.flags.mode == RunMode::Sandbox {
+            ctx.print(0, 1, self.tr(Text::SandboxHint));
+            return;
+        }
 
-            ctx.print_color_centered(15 + i * 2, color, RGBA::from_u8(0,0,0,0), &format!("{}{}", is_active, option));
+        ctx.print(0, 1, fill(self.tr(Text::HudLine), &[
+            &self.run.score,
+            &self.run.flags.difficulty.score_multiplier(),
+            &self.tr(self.run.flags.difficulty.label()),
+            &self.high_score,
+            &self.run.pipes_passed,
+            &self.run.coins,
+            &format_time(self.run.time_ms as i32),
+        ]));
+        // 进度条接在提示文字后面，各语言的提示长度不同
+        self.render_best_progress(ctx, flap_hint.chars().count() as i32 + 1, 0);
+        self.render_status_bar(ctx, 2);
+        if let Some(hot_seat) = &self.hot_seat {
+            ctx.print(0, 3, fill(self.tr(Text::HudPlayer), &[&(hot_seat.turn + 1)]));
+        }
+        if self.run.flags.mode == RunMode::Combo {
+            self.render_speed_gauge(ctx, 4);
+        }
+        if self.run.flags.mode == RunMode::Marathon {
+            let checkpoint = self.run.checkpoint.as_ref().map_or(0, |snapshot| snapshot.pipes_passed);
+            ctx.print(0, 4, fill(self.tr(Text::HudCheckpoint), &[&checkpoint, &self.run.checkpoint_retries]));
         }
     }
 
-    fn render_player_menu(&mut self, ctx: &mut BTerm) {
-        let options = vec![
-            "Dragon",
-            "Bird",
-            "Duck",
-            "Back",
-        ];
+    // 状态栏：剩余生命画成红心（已丢的画成暗色），无敌期间在后面画倒计时条
+    fn render_status_bar(&self, ctx: &mut BTerm, y: i32) {
+        let mut x = 0;
+        if self.settings.starting_lives > 1 {
+            ctx.print(x, y, self.tr(Text::HudLives));
+            for i in 0..self.settings.starting_lives {
+                let color = if i < self.run.lives { RED } else { DARK_GRAY };
+                ctx.set(7 + i, y, color, BLACK, 3); // cp437 中 3 号字符为红心
+            }
+            x = 8 + self.settings.starting_lives;
+        }
 
-        ctx.print_color_centered(12, WHITE, BLACK, "Select Player Style");
+        if self.run.invuln_remaining > 0.0 && self.run.invuln_duration > 0.0 {
+            let width = 10;
+            let filled = (self.run.invuln_remaining / self.run.invuln_duration * width as f32).ceil() as i32;
+            ctx.print(x, y, self.tr(Text::HudShield));
+            for i in 0..width {
+                let color = if i < filled { CYAN } else { DARK_GRAY };
+                ctx.set(x + 7 + i, y, color, BLACK, to_cp437('█'));
+            }
+            ctx.print(x + 8 + width, y, format!("{:.1}s", self.run.invuln_remaining / 1000.0));
+        }
+    }
 
-        for (i, option) in options.iter().enumerate() {
-            let color = if i as i32 == self.menu_state.selected_option {
-                YELLOW
+    // 离最高分还有多远：细条按 score / high_score 填充，超过后整条变金色；还没有最高分时不画
+    fn render_best_progress(&self, ctx: &mut BTerm, x: i32, y: i32) {
+        if self.high_score <= 0 {
+            return;
+        }
+        let width = 20;
+        let beaten = self.run.score > self.high_score;
+        let filled = (self.run.score as f32 / self.high_score as f32 * width as f32) as i32;
+        for i in 0..width {
+            let color = if beaten {
+                GOLD
+            } else if i < filled {
+                GREEN
             } else {
-                WHITE
-            };
-
-            let is_active = match (i, &self.settings.player_style) {
-                (0, PlayerStyle::Dragon) => "(*) ",
-                (1, PlayerStyle::Bird) => "(*) ",
-                (2, PlayerStyle::Duck) => "(*) ",
-                _ => "( ) ",
+                DARK_GRAY
             };
+            ctx.set(x + i, y, color, BLACK, to_cp437('▄'));
+        }
+    }
 
-            ctx.print_color_centered(15 + i * 2, color, RGBA::from_u8(0,0,0,0), &format!("{}{}", is_active, option));
+    // 连击模式的速度条
+    fn render_speed_gauge(&self, ctx: &mut BTerm, y: i32) {
+        let width = 20;
+        let progress = (self.combo_speed_factor() - 1.0) / (DEFAULT_PARAMETERS.combo_max_speed - 1.0);
+        let filled = (progress * width as f32).round() as i32;
+        ctx.print(0, y, self.tr(Text::HudSpeed));
+        for i in 0..width {
+            let color = if i < filled { ORANGE } else { DARK_GRAY };
+            ctx.set(6 + i, y, color, BLACK, to_cp437('█'));
         }
+        ctx.print(7 + width, y, fill(self.tr(Text::HudCombo), &[&format!("{:.2}", self.combo_speed_factor()), &self.run.clean_passes]));
     }
 
-    fn render_obstacle_menu(&mut self, ctx: &mut BTerm) {
-        ctx.print_centered(12, "Obstacle Distance");
-        ctx.print_centered(14, &format!("Current: {} spaces", self.settings.obstacle_distance));
-        ctx.print_centered(16, "(Use Left/Right to adjust)");
-        ctx.print_centered(18, "Back");
+    fn update_background(&mut self, ctx: &mut BTerm) {
+        if self.settings.reduced_motion {
+            return;
+        }
+        // 背景动画按固定间隔换帧，和滚动互不影响
+        self.background_frame_clock_ms += frame_time_ms(ctx);
+        if self.background_frame_clock_ms >= DEFAULT_PARAMETERS.background_frame_ms {
+            self.background_frame_clock_ms -= DEFAULT_PARAMETERS.background_frame_ms;
+            self.background_frame = self.background_frame.wrapping_add(1);
+        }
+        let step = DEFAULT_PARAMETERS.background_speed * self.chaos_effects().scroll_factor * frame_time_ms(ctx);
+        let (delta, wrap) = match self.settings.scroll_direction {
+            ScrollDirection::RightToLeft => (step, DEFAULT_PARAMETERS.screen_width),
+            ScrollDirection::LeftToRight => (-step, DEFAULT_PARAMETERS.screen_width),
+            ScrollDirection::Vertical => (-step, DEFAULT_PARAMETERS.screen_height),
+        };
+        // 反向滚动时偏移量会减到 0 以下，rem_euclid 保证它始终落在 0..wrap 内
+        self.run.background_offset = (self.run.background_offset + delta).rem_euclid(wrap as f32);
     }
 
-    fn handle_menu_input(&mut self, ctx: &mut BTerm) {
-        if let Some(key) = ctx.key {
-            match key {
-                VirtualKeyCode::Up => {
-                    if self.menu_state.selected_option > 0 {
-                        self.menu_state.selected_option -= 1;
-                    }
-                }
-                VirtualKeyCode::Down => {
-                    let max_options = match self.menu_state.current_menu {
-                        MainMenuOption::Main => 4,
-                        MainMenuOption::Background => 3,
-                        MainMenuOption::Player => 3,
-                        MainMenuOption::Obstacle => 1,
-                    };
+    fn playing(&mut self, ctx: &mut BTerm) {
+        // 按下扇翅膀键时飞起，按住快速下落键时落得更快
+        let flap = ctx.key == Some(self.settings.keys.flap) || self.assist_wants_flap();
+        let was_fast_falling = self.run.fast_falling;
+        self.run.fast_falling = INPUT.lock().is_key_pressed(self.settings.keys.fast_fall);
+        self.run.flap_held = INPUT.lock().is_key_pressed(self.settings.keys.flap);
+        // 松开后速度回落到正常的上限
+        if was_fast_falling && !self.run.fast_falling {
+            self.run.player.velocity = f32::min(self.run.player.velocity, self.physics().max_fall_speed);
+        }
+        if let Some(VirtualKeyCode::F3) = ctx.key {
+            self.settings.debug = !self.settings.debug;
+        }
+        if let Some(VirtualKeyCode::P) = ctx.key
+            && self.photo_mode_allowed()
+        {
+            self.enter_photo_mode();
+            // 这一帧的 P 已经用来进入拍照模式，只画出冻结的画面，下一帧起才处理拍照模式里的按键
+            self.render_photo(ctx);
+            return;
+        }
+        // 跳过结束界面直接重开，这一局不计分；双人轮流时不能用，否则等于多给自己一次机会
+        if ctx.key.is_some() && ctx.key == self.settings.keys.restart && self.hot_seat.is_none() {
+            self.restart();
+            return;
+        }
+        if let Some(VirtualKeyCode::Tab) = ctx.key {
+            self.quick_switch_style(ctx.shift);
+        }
+        self.update_time_scale(ctx);
+        self.update_combo_glow(ctx);
+        let dead = self.step(ctx, flap);
 
-                    if self.menu_state.selected_option < max_options {
-                        self.menu_state.selected_option += 1;
-                    }
+        if self.settings.debug {
+            self.render_hitboxes(ctx);
+        }
+        if self.settings.safe_zone {
+            self.render_safe_zone(ctx);
+        }
+        if self.settings.ideal_path {
+            self.render_ideal_path(ctx);
+        }
+        self.render_obstacle_warning(ctx);
+        self.render_combo_edges(ctx);
+        if self.settings.foreground {
+            self.render_foreground(ctx);
+        }
+
+        self.render_hud(ctx);
+
+        // 沙盒模式不计分，按 Esc 回到菜单
+        if self.run.flags.mode == RunMode::Sandbox {
+            if let Some(VirtualKeyCode::Escape) = ctx.key {
+                self.mode = GameMode::Menu;
+            }
+            return;
+        }
+
+        self.check_achievements();
+        self.render_toast(ctx);
+
+        if dead {
+            self.game_over();
+        }
+    }
+
+    // 开启管道附近减速时，玩家和任意管道有重叠的列就把时间放慢，离开后再平滑地恢复
+    fn update_time_scale(&mut self, ctx: &BTerm) {
+        let near_pipe = self.settings.near_pipe_slowdown
+            && self.run.obstacle_list.iter().any(|obstacle| obstacle.overlaps(&self.run.player));
+        let target = if near_pipe { DEFAULT_PARAMETERS.slowdown_factor } else { 1.0 };
+        let blend = f32::min(1.0, frame_time_ms(ctx) / DEFAULT_PARAMETERS.slowdown_blend_ms);
+        self.run.time_scale += (target - self.run.time_scale) * blend;
+    }
+
+    // 连续干净穿过的管道数超过门槛后特效逐级增强，到 combo_fx_full 时最强；
+    // 连击断了就在 combo_fx_fade_ms 内淡出，减少动态效果时直接关掉
+    fn update_combo_glow(&mut self, ctx: &BTerm) {
+        if self.settings.reduced_motion {
+            self.run.combo_glow = 0.0;
+            return;
+        }
+        let streak = self.run.clean_passes - DEFAULT_PARAMETERS.combo_fx_threshold + 1;
+        let levels = DEFAULT_PARAMETERS.combo_fx_full - DEFAULT_PARAMETERS.combo_fx_threshold + 1;
+        let target = (streak as f32 / levels as f32).clamp(0.0, 1.0);
+        let fade = frame_time_ms(ctx) / DEFAULT_PARAMETERS.combo_fx_fade_ms;
+        self.run.combo_glow = f32::max(target, self.run.combo_glow - fade);
+    }
+
+    // 连击特效的脉冲色：色相随时间转动，特效越强转得越快
+    fn combo_pulse_color(&self) -> RGB {
+        let period = DEFAULT_PARAMETERS.combo_fx_pulse_ms / (1.0 + self.run.combo_glow);
+        HSV::from_f32((self.run.time_ms / period).fract(), 0.8, 1.0).to_rgb()
+    }
+
+    // 背景往脉冲色混合的比例，只在游戏中生效
+    fn combo_tint(&self) -> f32 {
+        if !matches!(self.mode, GameMode::Playing) {
+            return 0.0;
+        }
+        self.run.combo_glow * DEFAULT_PARAMETERS.combo_fx_tint
+    }
+
+    // 游戏中不暂停地换外观：Tab 换下一个背景，Shift+Tab 换下一个已解锁的玩家。
+    // 玩家样式会改变手感，所以硬核局和双人轮流时不能换，其他局换过玩家就算用了辅助。
+    // background_offset 保持不变，新背景从同一个滚动位置接着滚，不会跳回开头
+    fn quick_switch_style(&mut self, player: bool) {
+        if self.run.flags.is_hardcore() || self.hot_seat.is_some() {
+            return;
+        }
+        if !player {
+            self.settings.background_style = self.settings.background_style.cycle(1);
+            return;
+        }
+        let current = PlayerStyle::ALL.iter().position(|style| *style == self.settings.player_style).unwrap_or(0);
+        if let Some(style) = (1..PlayerStyle::ALL.len())
+            .map(|step| PlayerStyle::ALL[(current + step) % PlayerStyle::ALL.len()])
+            .find(|style| self.unlocked_skins.contains(style))
+        {
+            self.settings.player_style = style;
+            self.run.flags.assists_used = true;
+        }
+    }
+
+    // 紧张度 0~1：分数从 chaos_start_score 到 chaos_full_score 线性增加，之后封顶。
+    // 只在游戏中生效，减少动态效果时始终为 0
+    fn chaos_level(&self) -> f32 {
+        if self.settings.reduced_motion || !matches!(self.mode, GameMode::Playing) {
+            return 0.0;
+        }
+        let progress = (self.run.score - DEFAULT_PARAMETERS.chaos_start_score) as f32;
+        let span = (DEFAULT_PARAMETERS.chaos_full_score - DEFAULT_PARAMETERS.chaos_start_score) as f32;
+        (progress / span).clamp(0.0, 1.0)
+    }
+
+    // 所有随紧张度变化的渲染参数都在这里算，整个画面一起升级
+    fn chaos_effects(&self) -> ChaosEffects {
+        let level = self.chaos_level();
+        ChaosEffects {
+            scroll_factor: 1.0 + level * DEFAULT_PARAMETERS.chaos_scroll,
+            tint: level * DEFAULT_PARAMETERS.chaos_tint,
+            detail: level * DEFAULT_PARAMETERS.chaos_detail,
+        }
+    }
+
+    // 屏幕左、右、上边缘的光晕，越靠边越亮；只改背景色，不盖住上面的管道和字
+    fn render_combo_edges(&self, ctx: &mut BTerm) {
+        if self.run.combo_glow <= 0.0 {
+            return;
+        }
+        let depth = (self.run.combo_glow * DEFAULT_PARAMETERS.combo_fx_edge as f32).ceil() as i32;
+        let pulse = self.combo_pulse_color();
+        let background = self.background_image();
+        let width = DEFAULT_PARAMETERS.screen_width;
+        // 地面有自己的颜色，光晕只画到地面上方
+        let bottom = i32::min(ground_top() - self.camera_offset_y, DEFAULT_PARAMETERS.screen_height);
+        for y in 0..bottom {
+            for x in 0..width {
+                let edge = x.min(width - 1 - x).min(y);
+                if edge >= depth {
+                    continue;
                 }
-                VirtualKeyCode::Return => {
-                    match self.menu_state.current_menu {
-                        MainMenuOption::Main => {
-                            match self.menu_state.selected_option {
-                                0 => self.restart(), // 开始游戏
-                                1 => {
-                                    self.menu_state.current_menu = MainMenuOption::Background;
-                                    self.menu_state.selected_option = 0;
-                                }
-                                2 => {
-                                    self.menu_state.current_menu = MainMenuOption::Player;
-                                    self.menu_state.selected_option = 0;
-                                }
-                                3 => {
-                                    self.menu_state.current_menu = MainMenuOption::Obstacle;
-                                    self.menu_state.selected_option = 0;
-                                }
-                                4 => ctx.quitting = true, // 退出游戏
-                                _ => {}
-                            }
-                        }
-                        MainMenuOption::Background => {
-                            match self.menu_state.selected_option {
-                                0 => self.settings.background_style = BackgroundStyle::Stars,
-                                1 => self.settings.background_style = BackgroundStyle::Clouds,
-                                2 => self.settings.background_style = BackgroundStyle::Mountains,
-                                3 => {
-                                    self.menu_state.current_menu = MainMenuOption::Main;
-                                    self.menu_state.selected_option = 1;
-                                }
-                                _ => {}
-                            }
-                        }
-                        MainMenuOption::Player => {
-                            match self.menu_state.selected_option {
-                                0 => self.settings.player_style = PlayerStyle::Dragon,
-                                1 => self.settings.player_style = PlayerStyle::Bird,
-                                2 => self.settings.player_style = PlayerStyle::Duck,
-                                3 => {
-                                    self.menu_state.current_menu = MainMenuOption::Main;
-                                    self.menu_state.selected_option = 2;
-                                }
-                                _ => {}
-                            }
-                        }
-                        MainMenuOption::Obstacle => {
-                            if self.menu_state.selected_option == 1 {
-                                self.menu_state.current_menu = MainMenuOption::Main;
-                                self.menu_state.selected_option = 3;
-                            }
-                        }
-                    }
+                let amount = self.run.combo_glow * (1.0 - edge as f32 / depth as f32);
+                let color = self.sample_background(background, x, y).lerp(pulse, f32::max(amount, self.combo_tint()));
+                ctx.set_bg(x, y, color);
+            }
+        }
+    }
+
+    fn render_ground(&self, ctx: &mut BTerm) {
+        for y in ground_top()..ground_top() + DEFAULT_PARAMETERS.ground_height {
+            for x in 0..DEFAULT_PARAMETERS.screen_width {
+                ctx.set(x, y - self.camera_offset_y, BLACK, ground_color(y), to_cp437(' '));
+            }
+        }
+    }
+
+    // 还在屏幕右侧外面的管道（最多 3 根），在右边缘从近到远各占一列画出淡淡的轮廓；
+    // 没开提示时只画因为速度太快而提前生成的管道
+    fn render_upcoming_obstacles(&self, ctx: &mut BTerm) {
+        let mut upcoming: Vec<&Obstacle> = self.run.obstacle_list
+            .iter()
+            .filter(|obstacle| obstacle.x >= DEFAULT_PARAMETERS.screen_width as f32)
+            .filter(|obstacle| self.settings.ghost_obstacles || obstacle.telegraph)
+            .collect();
+        upcoming.sort_by(|a, b| a.x.total_cmp(&b.x));
+
+        let viewport = Viewport::full(self.camera_offset_y);
+        let transparent = RGBA::from_u8(0, 0, 0, 0);
+        for (i, obstacle) in upcoming.iter().take(3).enumerate() {
+            let column = (DEFAULT_PARAMETERS.screen_width - 1 - i as i32 * 2) as f32;
+            for y in obstacle.solid_rows() {
+                if let Some((screen_x, screen_y)) = viewport.to_screen(column, y) {
+                    ctx.set(screen_x, screen_y, GRAY, transparent, to_cp437('░'));
                 }
-                VirtualKeyCode::Left => {
-                    if self.menu_state.current_menu == MainMenuOption::Obstacle && 
-                       self.menu_state.selected_option == 0 {
-                        self.settings.obstacle_distance = i32::max(40, self.settings.obstacle_distance - 5);
-                    }
+            }
+        }
+    }
+
+    // 双缺口和合拢管道刚生成、还没进屏幕多远时，在右边缘闪一列警告，让玩家提前准备。
+    // 减少动态效果时不闪，一直亮着
+    fn render_obstacle_warning(&self, ctx: &mut BTerm) {
+        let warning_x = (DEFAULT_PARAMETERS.screen_width - DEFAULT_PARAMETERS.warning_cells) as f32;
+        let upcoming = self.run.obstacle_list
+            .iter()
+            .any(|obstacle| obstacle.is_special() && obstacle.x >= warning_x);
+        if !upcoming {
+            return;
+        }
+        let blink_on = self.settings.reduced_motion
+            || (self.run.time_ms / DEFAULT_PARAMETERS.warning_blink_ms) as i32 % 2 == 0;
+        if !blink_on {
+            return;
+        }
+        let viewport = Viewport::full(self.camera_offset_y);
+        let background = self.background_image();
+        let column = DEFAULT_PARAMETERS.screen_width - 1;
+        // 管道已经进到这一列时只改背景色，不盖住管道
+        let covered = self.run.obstacle_list
+            .iter()
+            .any(|obstacle| (obstacle.left()..obstacle.right()).contains(&column));
+        for y in 0..ground_top() {
+            if let Some((screen_x, screen_y)) = viewport.to_screen(column as f32, y) {
+                let color = self.sample_background(background, screen_x, screen_y).lerp(RGB::named(ORANGE), 0.6);
+                if covered || y % 4 != 0 {
+                    ctx.set_bg(screen_x, screen_y, color);
+                } else {
+                    ctx.set(screen_x, screen_y, RED, color, to_cp437('!'));
                 }
-                VirtualKeyCode::Right => {
-                    if (self.menu_state.current_menu == MainMenuOption::Obstacle) && 
-                       self.menu_state.selected_option == 0 {
-                        self.settings.obstacle_distance = i32::min(60, self.settings.obstacle_distance + 5);
+            }
+        }
+    }
+
+    // 缺口高亮：前方最近一根管道的每个缺口在背景上叠一层淡绿色。
+    // 只改背景色；玩家贴图是用背景色画的，所以跳过玩家所在的格子
+    fn render_safe_zone(&self, ctx: &mut BTerm) {
+        let player = &self.run.player;
+        let Some(obstacle) = self.run.obstacle_list.iter().find(|obstacle| obstacle.right() > player.x) else {
+            return;
+        };
+        let viewport = Viewport::full(self.camera_offset_y);
+        let background = self.background_image();
+        for (top, bottom) in obstacle.gaps() {
+            for y in top..bottom {
+                for column in 0..obstacle.width {
+                    let x = obstacle.x + column as f32;
+                    let inside_player = (player.x..player.x + DEFAULT_PARAMETERS.player_width).contains(&(x as i32))
+                        && (player.y..player.y + DEFAULT_PARAMETERS.player_height).contains(&y);
+                    if inside_player {
+                        continue;
                     }
+                    if let Some((screen_x, screen_y)) = viewport.to_screen(x, y) {
+                        let color = self.sample_background(background, screen_x, screen_y)
+                            .lerp(RGB::named(GREEN), DEFAULT_PARAMETERS.safe_zone_opacity);
+                        ctx.set_bg(screen_x, screen_y, color);
+                    }
+                }
+            }
+        }
+    }
+
+    // 前景层：前景贴图的底边贴着屏幕底边，和背景一起滚动，在玩家和管道都画完之后再画。
+    // 不透明的像素整格盖住；半透明的像素只和背景混合后改背景色，格子里的字符还能透出来
+    fn render_foreground(&self, ctx: &mut BTerm) {
+        let Some(foreground) = &self.texture.foreground else {
+            return;
+        };
+        let width = foreground.width() as i32;
+        let top = DEFAULT_PARAMETERS.screen_height - foreground.height() as i32;
+        // 竖直滚动时前景固定不动，否则它会从屏幕底部滚出去
+        let offset = match self.settings.scroll_direction {
+            ScrollDirection::Vertical => 0,
+            _ => self.run.background_offset as i32,
+        };
+        let background = self.background_image();
+        let brightness = self.background_brightness();
+        for y in top.max(0)..DEFAULT_PARAMETERS.screen_height {
+            for x in 0..DEFAULT_PARAMETERS.screen_width {
+                let pixel = foreground.get_pixel((x + offset).rem_euclid(width) as u32, (y - top) as u32);
+                let alpha = pixel[3] as f32 / 255.0;
+                if alpha <= 0.0 {
+                    continue;
                 }
-                VirtualKeyCode::Escape => {
-                    self.menu_state.current_menu = MainMenuOption::Main;
-                    self.menu_state.selected_option = 0;
+                let color = RGB::from_u8(pixel[0], pixel[1], pixel[2]) * brightness;
+                if alpha >= 1.0 {
+                    ctx.set(x, y, color, color, to_cp437(' '));
+                } else {
+                    ctx.set_bg(x, y, self.sample_background(background, x, y).lerp(color, alpha));
                 }
-                _ => {}
             }
         }
     }
 
-    fn render_background(&self, ctx: &mut BTerm) {
-        match self.settings.background_style {
-            BackgroundStyle::Stars => self.render_stars_background(ctx),
-            BackgroundStyle::Clouds => self.render_clouds_background(ctx),
-            BackgroundStyle::Mountains => self.render_mountains_background(ctx),
+    // 建议路线：从玩家中心依次连到接下来几根管道的缺口中心，沿线每隔两列画一个暗点。
+    // 双缺口管道选离上一个点更近的那个缺口
+    fn render_ideal_path(&self, ctx: &mut BTerm) {
+        let player = &self.run.player;
+        let mut points = vec![(
+            (player.x + DEFAULT_PARAMETERS.player_width / 2) as f32,
+            (player.y + DEFAULT_PARAMETERS.player_height / 2) as f32,
+        )];
+        for obstacle in self.run.obstacle_list.iter().filter(|obstacle| obstacle.right() > player.x).take(DEFAULT_PARAMETERS.ideal_path_pipes) {
+            let (_, previous_y) = points[points.len() - 1];
+            let gap_y = obstacle.gaps()
+                .map(|(top, bottom)| (top + bottom) as f32 / 2.0)
+                .min_by(|a, b| (a - previous_y).abs().total_cmp(&(b - previous_y).abs()))
+                .unwrap_or(obstacle.gap_y as f32);
+            points.push((obstacle.x + obstacle.width as f32 / 2.0, gap_y));
+        }
+
+        let viewport = Viewport::full(self.camera_offset_y);
+        let transparent = RGBA::from_u8(0, 0, 0, 0);
+        for segment in points.windows(2) {
+            let ((x0, y0), (x1, y1)) = (segment[0], segment[1]);
+            let mut x = x0;
+            while x < x1 {
+                let y = y0 + (y1 - y0) * (x - x0) / (x1 - x0);
+                if let Some((screen_x, screen_y)) = viewport.to_screen(x, y.round() as i32) {
+                    ctx.set(screen_x, screen_y, DARK_GRAY, transparent, to_cp437('·'));
+                }
+                x += 2.0;
+            }
+        }
+    }
+
+    // 调试：画出玩家的碰撞框和每根管道缺口的上下边缘
+    fn render_hitboxes(&self, ctx: &mut BTerm) {
+        let transparent = RGBA::from_u8(0,0,0,0);
+        ctx.draw_hollow_box(
+            self.run.player.x,
+            self.run.player.y - self.camera_offset_y,
+            DEFAULT_PARAMETERS.player_width - 1,
+            DEFAULT_PARAMETERS.player_height - 1,
+            GREEN,
+            transparent,
+        );
+
+        for obstacle in &self.run.obstacle_list {
+            for (gap_top, gap_bottom) in obstacle.gaps() {
+                for edge_x in obstacle.left() - 2..obstacle.right() + 2 {
+                    ctx.set(edge_x, gap_top - 1 - self.camera_offset_y, MAGENTA, transparent, to_cp437('-'));
+                    ctx.set(edge_x, gap_bottom - self.camera_offset_y, MAGENTA, transparent, to_cp437('-'));
+                }
+            }
+        }
+
+        ctx.print_color_right(DEFAULT_PARAMETERS.screen_width - 1, 0, GREEN, BLACK, "DEBUG (F3)");
+    }
+
+    // 以简单难度开一局练习，教程期间成绩只进休闲榜
+    fn start_tutorial(&mut self) {
+        let difficulty = self.settings.difficulty;
+        let run_mode = self.settings.run_mode;
+        self.settings.difficulty = Difficulty::Easy;
+        self.settings.run_mode = RunMode::Classic;
+        self.restart();
+        self.settings.difficulty = difficulty;
+        self.settings.run_mode = run_mode;
+        self.run.flags.practice = true;
+        self.mode = GameMode::Tutorial;
+        self.tutorial_step = TutorialStep::Flap;
+    }
+
+    // 教程结束后写入标记文件，以后启动直接进菜单
+    fn finish_tutorial(&mut self) {
+        self.storage.write(TUTORIAL_MARKER, "");
+    }
+
+    fn tutorial(&mut self, ctx: &mut BTerm) {
+        if let Some(VirtualKeyCode::Escape) = ctx.key {
+            self.finish_tutorial();
+            self.mode = GameMode::Menu;
+            return;
+        }
+        let flap = ctx.key == Some(self.settings.keys.flap);
+
+        match self.tutorial_step {
+            // 第一步：画面静止，等玩家按一次扇翅膀键
+            TutorialStep::Flap => {
+                self.update_background(ctx);
+                self.render_background(ctx);
+                self.render_ground(ctx);
+                self.run.player.render(ctx, self.player_sprite(), self.camera_offset_y, self.player_mirrored(), self.settings.smooth_sprites, self.sprite_brightness());
+                ctx.print_color_centered_at(center_x(), 10, YELLOW, BLACK, fill(self.tr(Text::TutorialFlap), &[&self.key_name(self.settings.keys.flap)]));
+                if flap {
+                    self.play_flap_sound();
+                    self.run.player.flap(&self.physics());
+                    self.tutorial_step = TutorialStep::Gaps;
+                }
+            }
+            // 第二步：讲解管道缺口，通过第一根管道前死亡会从头再来
+            TutorialStep::Gaps => {
+                let dead = self.step(ctx, flap);
+                ctx.print_color_centered_at(center_x(), 8, YELLOW, BLACK, self.tr(Text::TutorialGaps));
+                ctx.print_color_centered_at(center_x(), 10, YELLOW, BLACK, self.tr(Text::TutorialAvoid));
+                if dead {
+                    self.start_tutorial();
+                } else if self.run.score > 0 {
+                    self.tutorial_step = TutorialStep::Done;
+                    self.tutorial_timer = 3000.0;
+                }
+            }
+            // 第三步：提示几秒后转为正常的一局
+            TutorialStep::Done => {
+                let dead = self.step(ctx, flap);
+                ctx.print_color_centered_at(center_x(), 10, YELLOW, BLACK, self.tr(Text::TutorialDone));
+                self.tutorial_timer -= frame_time_ms(ctx);
+                if dead {
+                    self.finish_tutorial();
+                    self.game_over();
+                } else if self.tutorial_timer <= 0.0 {
+                    self.finish_tutorial();
+                    self.mode = GameMode::Playing;
+                }
+            }
+        }
+
+        ctx.print(0, 1, fill(self.tr(Text::TutorialScore), &[&self.run.score, &self.run.pipes_passed]));
+        ctx.print(0, 0, self.tr(Text::SkipTutorial));
+    }
+
+    // 演示模式：由简单 AI 操控，成绩不记录，按任意键回到菜单
+    fn demo(&mut self, ctx: &mut BTerm) {
+        if ctx.key.is_some() {
+            self.mode = GameMode::Menu;
+            self.menu_idle_ms = 0.0;
+            return;
+        }
+
+        let flap = self.demo_wants_flap();
+        if self.step(ctx, flap) {
+            self.start_demo();
+        }
+
+        ctx.print_color_centered_at(center_x(), 2, YELLOW, BLACK, self.tr(Text::DemoHint));
+    }
+
+    // 演示总是经典玩法，沙盒里没有管道可看
+    fn start_demo(&mut self) {
+        let run_mode = self.settings.run_mode;
+        self.settings.run_mode = RunMode::Classic;
+        self.restart();
+        self.settings.run_mode = run_mode;
+        self.mode = GameMode::Demo;
+    }
+
+    // 自动扇翅膀辅助；完全辅助直接用演示 AI
+    fn assist_wants_flap(&self) -> bool {
+        match self.settings.flap_assist {
+            FlapAssist::Off => false,
+            FlapAssist::Full => self.demo_wants_flap(),
+            FlapAssist::Light => {
+                if self.run.player.velocity < 0.0 {
+                    return false;
+                }
+                // 下一根管道快到了就以它的缺口下沿为底，否则以地面为底
+                let bottom = self.run.player.y + DEFAULT_PARAMETERS.player_height;
+                let floor = self.run.obstacle_list.iter()
+                    .find(|obstacle| obstacle.right() >= self.run.player.x)
+                    .filter(|obstacle| obstacle.left() < self.run.player.x + DEFAULT_PARAMETERS.player_width * 2)
+                    .and_then(|obstacle| obstacle.gaps().map(|(_, gap_bottom)| gap_bottom).find(|gap_bottom| *gap_bottom >= bottom))
+                    .unwrap_or(DEFAULT_PARAMETERS.world_bottom);
+                bottom >= floor - DEFAULT_PARAMETERS.assist_margin
+            }
+        }
+    }
+
+    // 演示 AI：玩家中心低于下一个缺口中心且正在下落时就扇翅膀
+    fn demo_wants_flap(&self) -> bool {
+        let target_y = self.run.obstacle_list.iter()
+            .find(|obstacle| obstacle.right() >= self.run.player.x)
+            .map(|obstacle| obstacle.gap_y)
+            .unwrap_or(DEFAULT_PARAMETERS.screen_height / 2);
+        let center_y = self.run.player.y + DEFAULT_PARAMETERS.player_height / 2;
+        center_y > target_y + 2 && self.run.player.velocity >= 0.0
+    }
+
+    // 推进一帧并渲染游戏画面，返回玩家是否死亡
+    fn step(&mut self, ctx: &mut BTerm, flap: bool) -> bool {
+        self.update_background(ctx);
+        self.render_background(ctx); // 先渲染背景
+        
+        // 物理和管道按放慢后的时间推进，计时和输入缓冲仍按真实时间
+        self.run.frame_time += frame_time_ms(ctx) * self.run.time_scale;
+        self.run.time_ms += frame_time_ms(ctx);
+
+        if self.run.frame_time > self.run.flags.difficulty.frame_duration() {
+            self.record_snapshot();
+            // 按住扇翅膀键期间先向上加速，再照常受重力
+            if !self.run.flap_held {
+                self.run.flap_hold_remaining_ms = 0.0;
+            }
+            if self.run.flap_hold_remaining_ms > 0.0 {
+                self.run.player.boost(&self.physics());
+                self.run.flap_hold_remaining_ms -= self.run.flags.difficulty.frame_duration();
+            }
+            if let Some(zone) = self.run.wind_zones.iter().find(|zone| zone.contains(&self.run.player)) {
+                self.run.player.push(zone.force, &self.physics());
+            }
+            self.run.player.gravity_to_move(&self.physics());
+            self.run.frame_time = 0.0;
+        }
+
+        // 扇翅膀的输入先缓存一小段时间，冷却中稍微提前按下也不会丢
+        if flap {
+            self.run.buffered_flap_until = Some(self.run.time_ms + DEFAULT_PARAMETERS.flap_buffer_ms);
+        }
+        self.run.flap_cooldown_remaining = f32::max(0.0, self.run.flap_cooldown_remaining - frame_time_ms(ctx));
+        if let Some(until) = self.run.buffered_flap_until {
+            if self.run.time_ms > until {
+                self.run.buffered_flap_until = None;
+            } else if self.run.flap_cooldown_remaining <= 0.0 {
+                self.play_flap_sound();
+                if self.analog_flap() {
+                    self.run.player.tap(&self.physics());
+                    self.run.flap_hold_remaining_ms = DEFAULT_PARAMETERS.flap_hold_max_ms;
+                } else {
+                    self.run.player.flap(&self.physics());
+                }
+                self.run.flap_cooldown_remaining = self.settings.flap_cooldown_ms as f32;
+                self.run.buffered_flap_until = None;
+            }
+        }
+
+        self.run.invuln_remaining = f32::max(0.0, self.run.invuln_remaining - frame_time_ms(ctx));
+        let invulnerable = self.run.invuln_remaining > 0.0;
+
+        // 风区画在玩家和管道后面；减少动态效果时粒子不动
+        let drift = if self.settings.reduced_motion { 0 } else { (self.run.time_ms / DEFAULT_PARAMETERS.wind_particle_ms) as i32 };
+        let viewport = Viewport::full(self.camera_offset_y);
+        for zone in &self.run.wind_zones {
+            zone.render(ctx, &viewport, drift);
+        }
+
+        // 渲染玩家，无敌期间闪烁
+        if !invulnerable || (self.run.invuln_remaining / 100.0) as i32 % 2 == 0 {
+            self.run.player.render(ctx, self.player_sprite(), self.camera_offset_y, self.player_mirrored(), self.settings.smooth_sprites, self.sprite_brightness());
+        }
+
+        // 渲染障碍物
+        let speed = self.update_current_speed(ctx) * self.run.time_scale;
+        let trail = self.trail_intensity();
+        let multiplier = self.run.flags.difficulty.score_multiplier();
+        let mut dead = false;
+        let mut reached_checkpoint = false;
+        for obstacle in &mut self.run.obstacle_list {
+            obstacle.near_miss = obstacle.is_near_miss(&self.run.player);
+            if obstacle.near_miss && !obstacle.near_miss_awarded {
+                self.run.score += DEFAULT_PARAMETERS.near_miss_bonus * multiplier;
+                obstacle.near_miss_awarded = true;
+                self.run.clean_passes = 0;
+            }
+
+            obstacle.update(speed);
+            obstacle.render(ctx, &viewport, &self.settings.theme, trail);
+
+            if self.run.player.x >= obstacle.right() && !obstacle.scored {
+                self.run.score += multiplier;
+                self.run.pipes_passed += 1;
+                obstacle.scored = true; // 标记已得分
+                if !obstacle.near_miss_awarded {
+                    self.run.clean_passes += 1;
+                }
+                reached_checkpoint |= self.run.pipes_passed % DEFAULT_PARAMETERS.checkpoint_pipes == 0;
+            }
+
+            if !invulnerable && obstacle.hit_obstacle(&self.run.player) {
+                dead = true;
+            }
+        }
+        
+        self.render_upcoming_obstacles(ctx);
+
+        if reached_checkpoint && self.run.flags.mode == RunMode::Marathon {
+            self.run.checkpoint = Some(self.snapshot());
+            self.run.toasts.push_back(self.tr(Text::CheckpointReached).to_string());
+        }
+
+        for coin in &mut self.run.coin_list {
+            coin.update(speed);
+            coin.render(ctx, &viewport);
+        }
+        for zone in &mut self.run.wind_zones {
+            zone.update(speed);
+        }
+        let coins_before = self.run.coin_list.len();
+        self.run.coin_list.retain(|coin| !coin.touches(&self.run.player));
+        self.run.coins += (coins_before - self.run.coin_list.len()) as i32;
+
+        // 管道按生成顺序从左到右排列，移出屏幕的总在最前面
+        let retired = self.run.obstacle_list.iter().take_while(|obstacle| obstacle.is_offscreen()).count();
+        let retired: Vec<Obstacle> = self.run.obstacle_list.drain(..retired).collect();
+        self.recycle_obstacles(retired);
+        self.run.coin_list.retain(|coin| coin.x > 0.0);
+        self.run.wind_zones.retain(|zone| zone.right() > 0);
+        self.run.distance += speed;
+
+        if self.run.flags.mode != RunMode::Sandbox && self.run.distance > (self.settings.obstacle_distance + self.run.spacing_offset) as f32 {
+            let spawn_x = self.spawn_x(ctx, speed);
+            let mut obstacle = self.spawn_obstacle(spawn_x);
+            obstacle.telegraph = spawn_x > DEFAULT_PARAMETERS.screen_width;
+            if self.run.rng.range(0, 100) < DEFAULT_PARAMETERS.coin_chance {
+                self.run.coin_list.push(Coin::new(obstacle.x + (obstacle.width / 2) as f32, obstacle.gap_y));
+            }
+            // 先定下到下一根管道的额外间距，风区才能算准两根管道之间的正中
+            self.run.spacing_offset = self.roll_spacing_offset();
+            if self.curve_score() >= DEFAULT_PARAMETERS.wind_min_score
+                && self.run.rng.range(0, 100) < DEFAULT_PARAMETERS.wind_chance
+            {
+                // 放在这根管道和下一根之间的正中
+                let space = self.settings.obstacle_distance + self.run.spacing_offset - obstacle.width;
+                let x = obstacle.right() + (space - DEFAULT_PARAMETERS.wind_width) / 2;
+                let zone = WindZone::new(x as f32, &mut self.run.rng);
+                self.run.wind_zones.push(zone);
+            }
+            self.run.obstacle_list.push(obstacle);
+            self.run.distance = 0.0;
+        }
+
+        self.render_ground(ctx);
+
+        // 判断是否碰到地面或障碍物；沙盒模式下落到地面就停住
+        if self.run.player.y + DEFAULT_PARAMETERS.player_height > DEFAULT_PARAMETERS.world_bottom {
+            if self.run.flags.mode == RunMode::Sandbox {
+                self.run.player.y = DEFAULT_PARAMETERS.world_bottom - DEFAULT_PARAMETERS.player_height;
+                self.run.player.velocity = 0.0;
+            } else {
+                dead = true;
+            }
+        }
+
+        if self.settings.debug {
+            self.record_input(frame_time_ms(ctx), flap);
+        }
+
+        // 还有剩余生命时扣一条命并复活，而不是结束游戏
+        if dead && self.run.lives > 1 {
+            self.run.lives -= 1;
+            self.run.player = Player::start();
+            self.run.clean_passes = 0;
+            self.run.invuln_remaining = DEFAULT_PARAMETERS.respawn_invuln_ms;
+            self.run.invuln_duration = self.run.invuln_remaining;
+            dead = false;
+        }
+
+        // 马拉松模式命用完以后，还有重试次数就回到最近的检查点接着飞
+        if dead && self.run.checkpoint_retries > 0
+            && let Some(checkpoint) = self.run.checkpoint.clone()
+        {
+            self.restore_snapshot(checkpoint);
+            self.run.rewind_buffer.clear();
+            self.run.checkpoint_retries -= 1;
+            self.run.lives = self.settings.starting_lives;
+            self.run.invuln_remaining = DEFAULT_PARAMETERS.respawn_invuln_ms;
+            self.run.invuln_duration = self.run.invuln_remaining;
+            let message = fill(self.tr(Text::CheckpointRespawn), &[&self.run.checkpoint_retries]);
+            self.run.toasts.push_back(message);
+            dead = false;
+        }
+
+        dead
+    }
+
+    // 本局结束：更新最高分，并把成绩记到对应的排行榜
+    fn game_over(&mut self) {
+        self.mode = GameMode::End;
+        self.run.end_confirm_quit = false;
+
+        if self.run.score > self.high_score {
+            self.high_score = self.run.score;
+            self.storage.write("highscore.txt", &self.high_score.to_string()); // 保存到文件
+        }
+        self.last_score = Some(self.run.score);
+        self.storage.write(LAST_SCORE_FILE, &self.run.score.to_string());
+
+        let kind = self.run.flags.leaderboard_kind();
+        let score = self.run.score;
+        let entry = self.leaderboard_entry();
+        let storage = self.storage.clone();
+        let board = self.leaderboard_mut(kind);
+        if board.submit(entry) {
+            board.save(&storage);
+            self.run.recorded_on = Some(kind);
+        }
+
+        self.total_coins += self.run.coins;
+        self.storage.write(COINS_FILE, &self.total_coins.to_string());
+
+        if self.run.flags.records_adaptive() {
+            self.adaptive.record(self.run.time_ms);
+        }
+
+        self.run.unsubmitted = true;
+
+        if self.settings.debug && !self.run.input_log.is_empty() {
+            self.save_input_log();
+        }
+
+        // 双人模式下记下这一位的分数，转到换人/结果界面
+        if let Some(hot_seat) = &mut self.hot_seat {
+            hot_seat.scores[hot_seat.turn] = score;
+            self.mode = GameMode::HotSeat;
+        }
+    }
+
+    fn leaderboard_entry(&self) -> LeaderboardEntry {
+        LeaderboardEntry {
+            score: self.run.score,
+            pipes: self.run.pipes_passed,
+            time_ms: self.run.time_ms as i32,
+            difficulty: Some(self.run.flags.difficulty),
+        }
+    }
+
+    // 结束界面上还能倒带复活，所以成绩要等这局再也回不去了才提交：开新局、回菜单或退出时。
+    // 每局最多提交一次。返回后台线程，退出游戏时要等它发完
+    fn submit_final_score(&mut self) -> Option<std::thread::JoinHandle<()>> {
+        if std::mem::take(&mut self.run.unsubmitted) {
+            self.submit_score()
+        } else {
+            None
+        }
+    }
+
+    // 在后台线程里提交成绩，网络出错直接忽略，不会卡住界面
+    fn submit_score(&self) -> Option<std::thread::JoinHandle<()>> {
+        let url = self.settings.submit_url.clone()?;
+        let form = [
+            ("name", self.settings.player_name.clone()),
+            ("score", self.run.score.to_string()),
+            ("pipes", self.run.pipes_passed.to_string()),
+            ("time_ms", (self.run.time_ms as i32).to_string()),
+            ("difficulty", self.run.flags.difficulty.name().to_string()),
+            ("board", self.run.flags.leaderboard_kind().title().to_string()),
+        ];
+        Some(std::thread::spawn(move || {
+            let form: Vec<(&str, &str)> = form.iter().map(|(key, value)| (*key, value.as_str())).collect();
+            let _ = ureq::post(&url)
+                .timeout(std::time::Duration::from_secs(5))
+                .send_form(&form);
+        }))
+    }
+
+    fn start_hot_seat(&mut self) {
+        let seed = RandomNumberGenerator::new().next_u64();
+        self.hot_seat = Some(HotSeat {
+            turn: 0,
+            scores: [0, 0],
+            seed,
+        });
+        // 两位玩家用同一个等级，当前的等级不变
+        self.restart_at_level(seed, self.adaptive.level);
+    }
+
+    fn hot_seat_screen(&mut self, ctx: &mut BTerm) {
+        self.update_background(ctx);
+        self.render_background(ctx);
+
+        let language = self.settings.language;
+        let Some(hot_seat) = &mut self.hot_seat else {
+            self.mode = GameMode::Menu;
+            return;
+        };
+
+        // 第一位玩完，轮到第二位
+        if hot_seat.turn == 0 {
+            ctx.print_color_centered_at(center_x(), 5, WHITE, BLACK, fill(language.text(Text::HotSeatScored), &[&hot_seat.scores[0]]));
+            ctx.print_color_centered_at(center_x(), 7, YELLOW, BLACK, language.text(Text::HotSeatReady));
+            ctx.print_color_centered_at(center_x(), 9, WHITE, BLACK, language.text(Text::PressSpaceToStart));
+            if let Some(VirtualKeyCode::Space) = ctx.key {
+                hot_seat.turn = 1;
+                let seed = hot_seat.seed;
+                self.restart_at_level(seed, self.run.flags.adaptive_level);
+            }
+            return;
+        }
+
+        let [first, second] = hot_seat.scores;
+        let result = match first.cmp(&second) {
+            std::cmp::Ordering::Greater => Text::Player1Wins,
+            std::cmp::Ordering::Less => Text::Player2Wins,
+            std::cmp::Ordering::Equal => Text::Tie,
+        };
+        ctx.print_color_centered_at(center_x(), 5, YELLOW, BLACK, language.text(result));
+        ctx.print_color_centered_at(center_x(), 7, WHITE, BLACK, fill(language.text(Text::HotSeatResult), &[&1, &first]));
+        ctx.print_color_centered_at(center_x(), 8, WHITE, BLACK, fill(language.text(Text::HotSeatResult), &[&2, &second]));
+        ctx.print_color_centered_at(center_x(), 10, WHITE, BLACK, language.text(Text::PlayAgain));
+        ctx.print_color_centered_at(center_x(), 11, WHITE, BLACK, language.text(Text::MainMenuKey));
+
+        match ctx.key {
+            Some(VirtualKeyCode::P) => self.start_hot_seat(),
+            Some(VirtualKeyCode::M) => {
+                self.hot_seat = None;
+                self.mode = GameMode::Menu;
+            }
+            _ => {}
+        }
+    }
+
+    fn leaderboard_mut(&mut self, kind: LeaderboardKind) -> &mut Leaderboard {
+        match kind {
+            LeaderboardKind::Casual => &mut self.casual_board,
+            LeaderboardKind::Hardcore => &mut self.hardcore_board,
+            LeaderboardKind::CasualTime => &mut self.casual_time_board,
+            LeaderboardKind::HardcoreTime => &mut self.hardcore_time_board,
+            LeaderboardKind::Zen => &mut self.zen_board,
+        }
+    }
+
+    fn record_snapshot(&mut self) {
+        if self.run.rewind_buffer.len() >= DEFAULT_PARAMETERS.rewind_buffer_len {
+            self.run.rewind_buffer.pop_front();
+        }
+        let snapshot = self.snapshot();
+        self.run.rewind_buffer.push_back(snapshot);
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            player: self.run.player.clone(),
+            obstacle_list: self.run.obstacle_list.clone(),
+            score: self.run.score,
+            distance: self.run.distance,
+            spacing_offset: self.run.spacing_offset,
+            background_offset: self.run.background_offset,
+            rng: self.run.rng.clone(),
+            clean_passes: self.run.clean_passes,
+            pipes_passed: self.run.pipes_passed,
+            coin_list: self.run.coin_list.clone(),
+            wind_zones: self.run.wind_zones.clone(),
+            run_coins: self.run.coins,
+            run_time_ms: self.run.time_ms,
+        }
+    }
+
+    // 记下这一帧的输入和物理状态，超过 input_log_ms 的旧记录丢掉
+    fn record_input(&mut self, frame_time_ms: f32, flap: bool) {
+        let player = &self.run.player;
+        let center = player.y + DEFAULT_PARAMETERS.player_height / 2;
+        let nearest_gap = self.run.obstacle_list
+            .iter()
+            .find(|obstacle| obstacle.right() > player.x)
+            .and_then(|obstacle| obstacle.gaps().min_by_key(|(top, bottom)| ((top + bottom) / 2 - center).abs()));
+        self.run.input_log.push_back(InputLogEntry {
+            time_ms: self.run.time_ms,
+            frame_time_ms,
+            flap,
+            player_y: player.y,
+            velocity: player.velocity,
+            nearest_gap,
+        });
+        while self.run.input_log.front().is_some_and(|entry| self.run.time_ms - entry.time_ms > DEFAULT_PARAMETERS.input_log_ms) {
+            self.run.input_log.pop_front();
+        }
+    }
+
+    // 把输入记录写成 CSV，结果显示在结束界面上
+    fn save_input_log(&mut self) {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let file_name = format!("input_log_{}.csv", timestamp);
+        let mut lines = vec!["time_ms,frame_time_ms,flap,player_y,velocity,gap_top,gap_bottom".to_string()];
+        for entry in &self.run.input_log {
+            let (gap_top, gap_bottom) = entry.nearest_gap.map_or((String::new(), String::new()), |(top, bottom)| (top.to_string(), bottom.to_string()));
+            lines.push(format!(
+                "{:.1},{:.2},{},{},{:.3},{},{}",
+                entry.time_ms, entry.frame_time_ms, entry.flap as i32, entry.player_y, entry.velocity, gap_top, gap_bottom,
+            ));
+        }
+        self.run.end_message = Some(match fs::write(&file_name, lines.join("\n")) {
+            Ok(()) => fill(self.tr(Text::Saved), &[&file_name]),
+            Err(_) => self.tr(Text::InputLogFailed).to_string(),
+        });
+    }
+
+    // 死亡后倒带到大约 rewind_ms 之前继续游戏，本局会被标记为用过辅助
+    fn rewind(&mut self) {
+        let steps_back = (DEFAULT_PARAMETERS.rewind_ms / self.run.flags.difficulty.frame_duration()) as usize;
+        let index = self.run.rewind_buffer.len().saturating_sub(steps_back);
+        let Some(snapshot) = self.run.rewind_buffer.get(index).cloned() else {
+            return;
+        };
+        self.run.rewind_buffer.truncate(index);
+
+        // 撤销死亡时记下的成绩，等这局真正结束再重新记录
+        self.run.unsubmitted = false;
+        if let Some(kind) = self.run.recorded_on.take() {
+            let entry = self.leaderboard_entry();
+            let storage = self.storage.clone();
+            let board = self.leaderboard_mut(kind);
+            board.remove(entry);
+            board.save(&storage);
+        }
+        if self.high_score != self.run.high_score_before_run {
+            self.high_score = self.run.high_score_before_run;
+            self.storage.write("highscore.txt", &self.high_score.to_string());
+        }
+        self.total_coins -= self.run.coins;
+        self.storage.write(COINS_FILE, &self.total_coins.to_string());
+        if self.run.flags.records_adaptive() {
+            self.adaptive.undo_last();
+        }
+
+        self.restore_snapshot(snapshot);
+        self.run.invuln_remaining = 0.0;
+        self.run.end_message = None;
+        self.run.flags.assists_used = true;
+        self.mode = GameMode::Playing;
+    }
+
+    // 把局面恢复到快照时的样子，倒带和马拉松的检查点共用
+    fn restore_snapshot(&mut self, snapshot: Snapshot) {
+        self.run.player = snapshot.player;
+        let replaced = std::mem::replace(&mut self.run.obstacle_list, snapshot.obstacle_list);
+        self.recycle_obstacles(replaced);
+        self.run.score = snapshot.score;
+        self.run.distance = snapshot.distance;
+        self.run.spacing_offset = snapshot.spacing_offset;
+        self.run.background_offset = snapshot.background_offset;
+        self.run.rng = snapshot.rng;
+        self.run.clean_passes = snapshot.clean_passes;
+        self.run.pipes_passed = snapshot.pipes_passed;
+        self.run.coin_list = snapshot.coin_list;
+        self.run.wind_zones = snapshot.wind_zones;
+        self.run.coins = snapshot.run_coins;
+        self.run.time_ms = snapshot.run_time_ms;
+        self.run.current_speed = self.obstacle_speed();
+        self.run.buffered_flap_until = None;
+        self.run.frame_time = 0.0;
+    }
+
+    // 管道变窄的进度按未加权的分数算，困难难度的倍率不会让缺口缩得更快；禅模式始终按 0 分生成
+    fn curve_score(&self) -> i32 {
+        if self.run.flags.mode == RunMode::Zen {
+            return 0;
+        }
+        self.run.score / self.run.flags.difficulty.score_multiplier()
+    }
+
+    // 优先从对象池里取一根管道重新初始化，池空了才新建，两种方式得到的管道完全一样
+    fn spawn_obstacle(&mut self, x: i32) -> Obstacle {
+        match self.obstacle_pool.pop() {
+            Some(mut obstacle) => {
+                obstacle.reset(x, self.curve_score(), self.run.flags.gap_adjust(), &mut self.run.rng);
+                obstacle
+            }
+            None => Obstacle::new(x, self.curve_score(), self.run.flags.gap_adjust(), &mut self.run.rng),
+        }
+    }
+
+    fn recycle_obstacles(&mut self, obstacles: impl IntoIterator<Item = Obstacle>) {
+        for obstacle in obstacles {
+            if self.obstacle_pool.len() >= DEFAULT_PARAMETERS.obstacle_pool_max {
+                break;
+            }
+            self.obstacle_pool.push(obstacle);
+        }
+    }
+
+    // 用新的随机种子开局
+    fn restart(&mut self) {
+        let seed = self.next_seed.take().unwrap_or_else(|| RandomNumberGenerator::new().next_u64());
+        self.restart_with_seed(seed);
+    }
+
+    fn restart_with_seed(&mut self, seed: u64) {
+        // 只有选了自适应难度才调整，固定难度每局都完全一样
+        let adaptive_level = if self.settings.difficulty == Difficulty::Adaptive {
+            self.adaptive.start_run()
+        } else {
+            0.0
+        };
+        self.begin_run(seed, adaptive_level);
+    }
+
+    // 按给定的自适应等级开局，不调整等级，这局也不计入统计，同一个种子打出来的局面才完全一样
+    fn restart_at_level(&mut self, seed: u64, adaptive_level: f32) {
+        let adaptive_level = if self.settings.difficulty == Difficulty::Adaptive { adaptive_level } else { 0.0 };
+        self.begin_run(seed, adaptive_level);
+        self.run.flags.fixed_level = true;
+    }
+
+    fn begin_run(&mut self, seed: u64, adaptive_level: f32) {
+        let _ = self.submit_final_score();
+        let fresh = RunState::fresh_game(&self.settings, seed, self.high_score, adaptive_level);
+        let previous = std::mem::replace(&mut self.run, fresh);
+        self.recycle_obstacles(previous.obstacle_list);
+        self.mode = GameMode::Playing;
+        self.run.current_speed = self.obstacle_speed(); // 开局直接用目标速度，不从 0 加速
+        // 第一根管道要从对象池里取，所以放在重建之后生成
+        let first = self.spawn_obstacle(DEFAULT_PARAMETERS.screen_width + self.run.flags.difficulty.first_obstacle_runway());
+        if self.run.flags.mode == RunMode::Sandbox {
+            self.recycle_obstacles([first]);
+        } else {
+            self.run.obstacle_list.push(first);
+        }
+    }
+
+    fn end(&mut self, ctx: &mut BTerm) {
+        self.update_background(ctx);
+        self.render_background(ctx); // 渲染背景
+
+        ctx.print_color_centered_at(center_x(), 5, WHITE,BLACK,self.tr(Text::YouAreDead));
+        ctx.print_color_centered_at(center_x(), 6, WHITE,BLACK,fill(self.tr(Text::FinalScore), &[&self.run.score, &self.run.pipes_passed, &format_time(self.run.time_ms as i32), &self.run.coins, &self.total_coins]));
+        ctx.print_color_centered_at(center_x(), 7,WHITE,BLACK, fill(self.tr(Text::HighScore), &[&self.high_score])); // 显示历史最高分
+        ctx.print_color_centered_at(center_x(), 8,WHITE,BLACK, fill(self.tr(Text::RecordedOn), &[&self.tr(self.run.flags.leaderboard_kind().label())]));
+        ctx.print_color_centered_at(center_x(), 9,WHITE,BLACK, self.tr(Text::PlayAgainRetry));
+        ctx.print_color_centered_at(center_x(), 10, WHITE,BLACK,self.tr(Text::MainMenuKey));
+        if self.run.end_confirm_quit {
+            ctx.print_color_centered_at(center_x(), 11, YELLOW, BLACK, self.tr(Text::ReallyQuit));
+        } else {
+            ctx.print_color_centered_at(center_x(), 11, WHITE,BLACK,self.tr(Text::QuitGameKey));
+        }
+        ctx.print_color_centered_at(center_x(), 12, WHITE,BLACK,self.tr(Text::SaveScreenshotKey));
+        if !self.run.rewind_buffer.is_empty() {
+            ctx.print_color_centered_at(center_x(), 13, WHITE,BLACK,self.tr(Text::RewindKeys));
+        }
+        if let Some(message) = &self.run.end_message {
+            ctx.print_color_centered_at(center_x(), 14, YELLOW, BLACK, message);
+        }
+        let share_code = ShareCode {
+            seed: self.run.seed,
+            difficulty: self.run.flags.difficulty,
+            run_mode: self.run.flags.mode,
+            obstacle_distance: self.settings.obstacle_distance,
+            adaptive_level: (self.run.flags.difficulty == Difficulty::Adaptive).then_some(self.run.flags.adaptive_level),
+            spacing_jitter: self.settings.spacing_jitter,
+        };
+        ctx.print_color_centered_at(center_x(), 16, WHITE, BLACK, fill(self.tr(Text::ShareCodeLine), &[&share_code.encode()]));
+        ctx.print_color_centered_at(center_x(), 17, WHITE, BLACK, fill(self.tr(Text::SeedLine), &[&self.run.seed]));
+
+        // 确认退出时只有 Y 或回车才退出，其他任何键都取消
+        if self.run.end_confirm_quit {
+            if let Some(key) = ctx.key {
+                match key {
+                    VirtualKeyCode::Y | VirtualKeyCode::Return => ctx.quitting = true,
+                    _ => self.run.end_confirm_quit = false,
+                }
+            }
+            return;
+        }
+
+        // 判断按键
+        if let Some(key) = ctx.key {
+            match key {
+                VirtualKeyCode::P => self.restart(),
+                VirtualKeyCode::T => self.restart_at_level(self.run.seed, self.run.flags.adaptive_level),
+                VirtualKeyCode::M => {
+                    let _ = self.submit_final_score();
+                    self.mode = GameMode::Menu;
+                }
+                VirtualKeyCode::Q => self.run.end_confirm_quit = true, // 死后连按容易误触，先确认
+                VirtualKeyCode::S => self.save_screenshot(),
+                VirtualKeyCode::B => self.rewind(),
+                VirtualKeyCode::G if !self.run.rewind_buffer.is_empty() => self.save_replay_gif(),
+                VirtualKeyCode::C => self.copy_seed(),
+                key if Some(key) == self.settings.keys.restart => self.restart(),
+                _ => {}
+            }
+        }
+    }
+
+    // 复制失败不影响游戏，把种子打印到控制台，照样能抄下来
+    fn copy_seed(&mut self) {
+        let seed = self.run.seed.to_string();
+        let message = if copy_to_clipboard(&seed) {
+            Text::SeedCopied
+        } else {
+            println!("Seed: {}", seed);
+            Text::SeedCopyFailed
+        };
+        self.run.end_message = Some(self.tr(message).to_string());
+    }
+
+    // 截图文件名带上分数和时间戳
+    fn save_screenshot(&mut self) {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let file_name = format!("screenshot_{}_{}.png", self.run.score, timestamp);
+        self.run.end_message = Some(match self.compose_frame(DEFAULT_PARAMETERS.screenshot_scale).save(&file_name) {
+            Ok(()) => fill(self.tr(Text::Saved), &[&file_name]),
+            Err(_) => self.tr(Text::ScreenshotFailed).to_string(),
+        });
+    }
+
+    // 把倒带缓冲里录下的最后几秒加上当前画面导出成循环播放的 GIF
+    fn save_replay_gif(&mut self) {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let file_name = format!("replay_{}_{}.gif", self.run.score, timestamp);
+        let scale = DEFAULT_PARAMETERS.gif_scale;
+        let skip = self.run.rewind_buffer.len().saturating_sub(DEFAULT_PARAMETERS.gif_max_frames - 1);
+        let mut frames: Vec<image::RgbaImage> = self.run.rewind_buffer
+            .iter()
+            .skip(skip)
+            .map(|snapshot| self.compose_snapshot(snapshot, scale))
+            .collect();
+        frames.push(self.compose_frame(scale));
+
+        // 快照是每个物理帧录一份，按物理步长播放就是原速
+        let delay = image::Delay::from_numer_denom_ms(self.run.flags.difficulty.frame_duration() as u32, 1);
+        let result = File::create(&file_name).map_err(image::ImageError::IoError).and_then(|file| {
+            let mut encoder = image::codecs::gif::GifEncoder::new_with_speed(file, 10);
+            encoder.set_repeat(image::codecs::gif::Repeat::Infinite)?;
+            encoder.encode_frames(frames.into_iter().map(|frame| image::Frame::from_parts(frame, 0, 0, delay)))
+        });
+        self.run.end_message = Some(match result {
+            Ok(()) => fill(self.tr(Text::Saved), &[&file_name]),
+            Err(_) => self.tr(Text::ReplayFailed).to_string(),
+        });
+    }
+
+    fn compose_frame(&self, scale: u32) -> image::RgbaImage {
+        self.compose_snapshot(&self.snapshot(), scale)
+    }
+
+    // 按格子重建一个局面的画面（背景、管道、玩家），每格放大为 scale x scale 像素
+    fn compose_snapshot(&self, snapshot: &Snapshot, scale: u32) -> image::RgbaImage {
+        cells_to_image(&self.snapshot_cells(snapshot), scale)
+    }
+
+    // 一个局面逐格的颜色，以及该格是否画管道的竖线，按行排列
+    fn snapshot_cells(&self, snapshot: &Snapshot) -> Vec<(RGB, bool)> {
+        let width = DEFAULT_PARAMETERS.screen_width;
+        let height = DEFAULT_PARAMETERS.screen_height;
+        let background = self.background_image();
+
+        // 每格的底色，以及是否画管道的竖线
+        let mut cells: Vec<(RGB, bool)> = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                cells.push((self.background_color(background, x, y, snapshot.background_offset), false));
+            }
+        }
+
+        for obstacle in &snapshot.obstacle_list {
+            for x in obstacle.left().max(0)..obstacle.right().min(width) {
+                for y in obstacle.solid_rows() {
+                    let screen_y = y - self.camera_offset_y;
+                    if screen_y < 0 || screen_y >= height {
+                        continue;
+                    }
+                    cells[(screen_y * width + x) as usize] = (RGB::named(YELLOW), true);
+                }
+            }
+        }
+
+        for coin in &snapshot.coin_list {
+            let x = coin.x as i32;
+            let screen_y = coin.y - self.camera_offset_y;
+            if x < 0 || x >= width || screen_y < 0 || screen_y >= height {
+                continue;
+            }
+            cells[(screen_y * width + x) as usize] = (RGB::named(GOLD), false);
+        }
+
+        for y in ground_top()..ground_top() + DEFAULT_PARAMETERS.ground_height {
+            let screen_y = y - self.camera_offset_y;
+            if screen_y < 0 || screen_y >= height {
+                continue;
+            }
+            for x in 0..width {
+                cells[(screen_y * width + x) as usize] = (ground_color(y), false);
+            }
+        }
+
+        let sprite = self.player_sprite();
+        let mirrored = self.player_mirrored();
+        let brightness = self.sprite_brightness();
+        for y in 0..DEFAULT_PARAMETERS.player_height {
+            for x in 0..DEFAULT_PARAMETERS.player_width {
+                let screen_x = snapshot.player.x + x;
+                let screen_y = snapshot.player.y + y - self.camera_offset_y;
+                if screen_x < 0 || screen_x >= width || screen_y < 0 || screen_y >= height {
+                    continue;
+                }
+                let pixel = sample_sprite(sprite, x, y, mirrored, self.settings.smooth_sprites);
+                if pixel[3] == 0 {
+                    continue;
+                }
+                cells[(screen_y * width + screen_x) as usize] = (RGB::from_u8(pixel[0], pixel[1], pixel[2]) * brightness, false);
+            }
+        }
+
+        cells
+    }
+
+    // 拍照时整局都停住，在硬核局和双人轮流里等于随时暂停想一想，所以不能用
+    fn photo_mode_allowed(&self) -> bool {
+        !self.run.flags.is_hardcore() && self.hot_seat.is_none()
+    }
+
+    // 冻结当前画面进入拍照模式，本局的时间在此期间不走
+    fn enter_photo_mode(&mut self) {
+        self.photo = Some(PhotoMode {
+            cells: self.snapshot_cells(&self.snapshot()),
+            pan_x: 0.0,
+            pan_y: 0.0,
+            zoom: 1.0,
+            message: None,
+        });
+        self.mode = GameMode::Photo;
+    }
+
+    // 方向键平移，+/- 缩放，0 复位，S 按当前取景保存截图，Esc 或 P 回到游戏
+    fn photo_mode(&mut self, ctx: &mut BTerm) {
+        let language = self.settings.language;
+        let Some(photo) = &mut self.photo else {
+            self.mode = GameMode::Playing;
+            return;
+        };
+        // 放大后每次按键在屏幕上移动的距离不变
+        let step = DEFAULT_PARAMETERS.photo_pan_step / photo.zoom;
+        match ctx.key {
+            Some(VirtualKeyCode::Left) => photo.pan_x -= step,
+            Some(VirtualKeyCode::Right) => photo.pan_x += step,
+            Some(VirtualKeyCode::Up) => photo.pan_y -= step,
+            Some(VirtualKeyCode::Down) => photo.pan_y += step,
+            Some(VirtualKeyCode::Equals | VirtualKeyCode::Plus | VirtualKeyCode::NumpadAdd) => {
+                photo.zoom = f32::min(photo.zoom + 0.5, DEFAULT_PARAMETERS.photo_max_zoom);
+            }
+            Some(VirtualKeyCode::Minus | VirtualKeyCode::NumpadSubtract) => photo.zoom = f32::max(photo.zoom - 0.5, 1.0),
+            Some(VirtualKeyCode::Key0) => {
+                photo.pan_x = 0.0;
+                photo.pan_y = 0.0;
+                photo.zoom = 1.0;
+            }
+            Some(VirtualKeyCode::S) => {
+                let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+                let file_name = format!("photo_{}.png", timestamp);
+                photo.message = Some(match cells_to_image(&photo.view(), DEFAULT_PARAMETERS.screenshot_scale).save(&file_name) {
+                    Ok(()) => fill(language.text(Text::Saved), &[&file_name]),
+                    Err(_) => language.text(Text::PhotoFailed).to_string(),
+                });
+            }
+            Some(VirtualKeyCode::Escape | VirtualKeyCode::P) => {
+                self.photo = None;
+                self.mode = GameMode::Playing;
+                return;
+            }
+            _ => {}
+        }
+        self.render_photo(ctx);
+    }
+
+    // 按当前取景画出冻结的画面，底部是操作提示和保存结果
+    fn render_photo(&self, ctx: &mut BTerm) {
+        let Some(photo) = &self.photo else {
+            return;
+        };
+        let language = self.settings.language;
+        let width = DEFAULT_PARAMETERS.screen_width;
+        for (i, (color, pipe)) in photo.view().into_iter().enumerate() {
+            let glyph = if pipe { to_cp437('|') } else { to_cp437(' ') };
+            ctx.set(i as i32 % width, i as i32 / width, RED, color, glyph);
+        }
+        let bottom = DEFAULT_PARAMETERS.screen_height - 1;
+        ctx.print_color(0, bottom, WHITE, BLACK, fill(language.text(Text::PhotoHint), &[&format!("{:.1}", photo.zoom)]));
+        if let Some(message) = &photo.message {
+            ctx.print_color_right(width - 1, bottom, YELLOW, BLACK, message);
+        }
+    }
+
+    fn main_menu(&mut self, ctx: &mut BTerm) {
+        self.render_background(ctx); // 渲染背景
+        self.update_background(ctx);
+        // 渲染标题
+        for (x, y, char) in &self.texture.menu_title {
+            ctx.set(*x, *y, YELLOW, RGBA::from_u8(0,0,0,0), *char);
+        }
+        let last = self.last_score.map_or("-".to_string(), |score| score.to_string());
+        ctx.print_color_centered_at(center_x(), 9, WHITE, RGBA::from_u8(0,0,0,0), fill(self.tr(Text::LastAndBest), &[&last, &self.high_score]));
+
+        // 菜单文字后面垫一块半透明的暗色面板，保证在亮色背景上也看得清
+        self.render_panel(ctx, 30, 11, 60, 32, 0.7);
+
+        // 根据当前菜单状态渲染不同的菜单
+        match self.menu_state.current_menu {
+            MainMenuOption::Main => self.render_main_menu(ctx),
+            MainMenuOption::Background => self.render_background_menu(ctx),
+            MainMenuOption::Player => self.render_player_menu(ctx),
+            MainMenuOption::Obstacle => self.render_obstacle_menu(ctx),
+            MainMenuOption::Leaderboard => self.render_leaderboard_menu(ctx),
+            MainMenuOption::Options => self.render_options_menu(ctx),
+            MainMenuOption::ShareCode => self.render_share_code_menu(ctx),
+            MainMenuOption::Achievements => self.render_achievements_menu(ctx),
+            MainMenuOption::Profiles => self.render_profiles_menu(ctx),
+            MainMenuOption::NewProfile => self.render_new_profile_menu(ctx),
+        }
+
+        // 处理菜单导航
+        self.handle_menu_input(ctx);
+
+        // 闲置一段时间后自动进入演示
+        if ctx.key.is_some() {
+            self.menu_idle_ms = 0.0;
+        } else {
+            self.menu_idle_ms += frame_time_ms(ctx);
+        }
+        if matches!(self.mode, GameMode::Menu) && self.menu_idle_ms > DEFAULT_PARAMETERS.demo_idle_ms {
+            self.menu_idle_ms = 0.0;
+            self.start_demo();
+        }
+    }
+
+    fn render_main_menu(&mut self, ctx: &mut BTerm) {
+        let difficulty = format!("{}: < {} >", self.tr(Text::Difficulty), self.tr(self.settings.difficulty.label()));
+        let run_mode = format!("{}: < {} >", self.tr(Text::Mode), self.tr(self.settings.run_mode.label()));
+        let profile = fill(self.tr(Text::ProfileOption), &[&self.storage.profile]);
+        let options = [
+            self.tr(Text::StartGame),
+            self.tr(Text::BackgroundStyle),
+            self.tr(Text::PlayerStyle),
+            self.tr(Text::ObstacleDistance),
+            difficulty.as_str(),
+            run_mode.as_str(),
+            self.tr(Text::Leaderboard),
+            self.tr(Text::Achievements),
+            self.tr(Text::Options),
+            self.tr(Text::TwoPlayers),
+            self.tr(Text::EnterShareCode),
+            profile.as_str(),
+            self.tr(if self.menu_state.confirm_quit { Text::ReallyQuit } else { Text::QuitGame }),
+        ];
+
+        for (i, option) in options.iter().enumerate() {
+            let color = self.settings.theme.menu_item_color(i as i32 == self.menu_state.selected_option);
+
+            ctx.print_color_centered_at(center_x(), 15 + i * 2, color, RGBA::from_u8(0,0,0,0), option);
+        }
+        ctx.print_color_centered_at(center_x(), 41, WHITE, RGBA::from_u8(0,0,0,0), self.tr(Text::MenuShortcuts));
+
+        // 缺口曲线只取决于难度和玩法，光标停在这两项上时在面板下面画出来，左右切换马上能看到变化
+        if matches!(self.menu_state.selected_option, 4 | 5) {
+            self.render_panel(ctx, 30, 44, 60, 10, 0.7);
+            self.render_gap_curve(ctx, 45);
+        }
+    }
+
+    // 存档列表，后面是 New Profile 和 Back；当前存档标 (*)
+    fn render_profiles_menu(&mut self, ctx: &mut BTerm) {
+        ctx.print_color_centered_at(center_x(), 12, WHITE, BLACK, self.tr(Text::Profiles));
+        let count = self.profiles.len();
+        for (i, name) in self.profiles.iter().enumerate() {
+            let color = self.settings.theme.menu_item_color(i as i32 == self.menu_state.selected_option);
+            let marker = if *name == self.storage.profile { "(*) " } else { "( ) " };
+            ctx.print_color_centered_at(center_x(), 15 + i * 2, color, RGBA::from_u8(0,0,0,0), format!("{}{}", marker, name));
+        }
+        for (i, option) in [self.tr(Text::NewProfile), self.tr(Text::Back)].iter().enumerate() {
+            let row = count + i;
+            let color = self.settings.theme.menu_item_color(row as i32 == self.menu_state.selected_option);
+            ctx.print_color_centered_at(center_x(), 15 + row * 2, color, RGBA::from_u8(0,0,0,0), option);
+        }
+
+        let bottom = 17 + (count + 2) * 2;
+        if self.menu_state.confirm_delete
+            && let Some(name) = self.profiles.get(self.menu_state.selected_option as usize)
+        {
+            ctx.print_color_centered_at(center_x(), bottom, YELLOW, BLACK, fill(self.tr(Text::ReallyDeleteProfile), &[name]));
+        } else if let Some(error) = &self.profile_error {
+            ctx.print_color_centered_at(center_x(), bottom, RED, BLACK, error);
+        } else {
+            ctx.print_color_centered_at(center_x(), bottom, WHITE, RGBA::from_u8(0,0,0,0), self.tr(Text::ProfilesHint));
+        }
+    }
+
+    fn render_new_profile_menu(&mut self, ctx: &mut BTerm) {
+        ctx.print_color_centered_at(center_x(), 12, WHITE, BLACK, self.tr(Text::NewProfile));
+        self.profile_name_input.render(ctx, 15, YELLOW);
+        if let Some(error) = &self.profile_error {
+            ctx.print_color_centered_at(center_x(), 17, RED, BLACK, error);
+        }
+        ctx.print_color_centered_at(center_x(), 19, WHITE, RGBA::from_u8(0,0,0,0), self.tr(Text::ProfileNameHint));
+    }
+
+    // 打开存档菜单时重新扫描一遍目录，光标停在当前存档上
+    fn open_profiles_menu(&mut self) {
+        self.profiles = self.storage.profiles();
+        self.profile_error = None;
+        self.menu_state.confirm_delete = false;
+        self.open_submenu(MainMenuOption::Profiles, 11);
+        self.menu_state.selected_option =
+            self.profiles.iter().position(|name| *name == self.storage.profile).unwrap_or(0) as i32;
+    }
+
+    // 新建存档界面返回存档菜单时不经过主菜单，进主菜单前记下的光标位置保持不变
+    fn return_to_profiles_menu(&mut self) {
+        self.profiles = self.storage.profiles();
+        self.menu_state.current_menu = MainMenuOption::Profiles;
+        self.menu_state.selected_option = self.profiles.len() as i32;
+    }
+
+    // 名字合法且还没有同名存档时建好并切换过去，否则只显示错误
+    fn submit_profile_name(&mut self) {
+        let name = self.profile_name_input.text().trim().to_string();
+        if !valid_profile_name(&name) {
+            self.profile_error = Some(self.tr(Text::ProfileNameInvalid).to_string());
+        } else if self.storage.profiles().contains(&name) {
+            self.profile_error = Some(self.tr(Text::ProfileExists).to_string());
+        } else {
+            self.storage.create_profile(&name);
+            self.switch_profile(&name);
+            self.profile_error = None;
+            self.close_submenu();
+        }
+    }
+
+    // 删除存档菜单里选中的存档；删的是当前存档时回到 default
+    fn delete_selected_profile(&mut self) {
+        let Some(name) = self.profiles.get(self.menu_state.selected_option as usize).cloned() else {
+            return;
+        };
+        self.storage.delete_profile(&name);
+        if name == self.storage.profile {
+            self.switch_profile(DEFAULT_PROFILE);
+        }
+        self.profiles = self.storage.profiles();
+        self.menu_state.selected_option = self.menu_state.selected_option.min(self.profiles.len() as i32 + 1);
+    }
+
+    fn render_background_menu(&mut self, ctx: &mut BTerm) {
+        let options = [
+            self.tr(Text::Stars),
+            self.tr(Text::Clouds),
+            self.tr(Text::Mountains),
+            self.tr(Text::Back),
+        ];
+
+        ctx.print_color_centered_at(center_x(), 12, WHITE,BLACK,self.tr(Text::SelectBackground));
+
+        for (i, option) in options.iter().enumerate() {
+            let color = self.settings.theme.menu_item_color(i as i32 == self.menu_state.selected_option);
+
+            let is_active = match (i, &self.settings.background_style) {
+                (0, BackgroundStyle::Stars) => "(*) ",
+                (1, BackgroundStyle::Clouds) => "(*) ",
+                (2, BackgroundStyle::Mountains) => "(*) ",
+                _ => "( ) ",
+            };
+
+            ctx.print_color_centered_at(center_x(), 15 + i * 2, color, RGBA::from_u8(0,0,0,0), format!("{}{}", is_active, option));
+        }
+    }
+
+    fn render_player_menu(&mut self, ctx: &mut BTerm) {
+        ctx.print_color_centered_at(center_x(), 12, WHITE, BLACK, self.tr(Text::SelectPlayer));
+        ctx.print_color_centered_at(center_x(), 13, GOLD, BLACK, fill(self.tr(Text::CoinsTotal), &[&self.total_coins]));
+
+        // 未解锁的皮肤灰显并标出价格
+        for (i, style) in PlayerStyle::ALL.iter().enumerate() {
+            let selected = i as i32 == self.menu_state.selected_option;
+            let unlocked = self.unlocked_skins.contains(style);
+            let color = if unlocked || selected { self.settings.theme.menu_item_color(selected) } else { RGB::named(DARK_GRAY) };
+            let label = if !unlocked {
+                fill(self.tr(Text::LockedSkin), &[&self.tr(style.label()), &style.unlock_cost()])
+            } else if *style == self.settings.player_style {
+                format!("(*) {}", self.tr(style.label()))
+            } else {
+                format!("( ) {}", self.tr(style.label()))
+            };
+            ctx.print_color_centered_at(center_x(), 15 + i * 2, color, RGBA::from_u8(0,0,0,0), label);
+        }
+        let back = PlayerStyle::ALL.len() as i32;
+        let color = self.settings.theme.menu_item_color(back == self.menu_state.selected_option);
+        ctx.print_color_centered_at(center_x(), 15 + back * 2, color, RGBA::from_u8(0,0,0,0), format!("( ) {}", self.tr(Text::Back)));
+
+        if let Some(style) = PlayerStyle::ALL.get(self.menu_state.selected_option as usize)
+            && !self.unlocked_skins.contains(style)
+        {
+            let hint = if self.total_coins >= style.unlock_cost() {
+                fill(self.tr(Text::UnlockFor), &[&style.unlock_cost()])
+            } else {
+                fill(self.tr(Text::NeedMoreCoins), &[&(style.unlock_cost() - self.total_coins)])
+            };
+            ctx.print_color_centered_at(center_x(), 24, GOLD, BLACK, hint);
+        }
+    }
+
+    // 清空最高分、最近一局的分数、所有排行榜、金币、已解锁的皮肤和成就，存档文件一并删除（皮肤文件改写成只有免费皮肤）；不影响设置和教程标记
+    fn reset_progress(&mut self) {
+        self.high_score = 0;
+        self.run.high_score_before_run = 0;
+        self.storage.remove("highscore.txt");
+        self.last_score = None;
+        self.storage.remove(LAST_SCORE_FILE);
+
+        for kind in LeaderboardKind::ALL {
+            self.leaderboard_mut(kind).entries.clear();
+            self.storage.remove(kind.file_name());
+        }
+
+        self.total_coins = 0;
+        self.storage.remove(COINS_FILE);
+        // 皮肤文件不删，改写成只有免费皮肤，否则下次启动会被当成旧存档而全部解锁
+        self.unlocked_skins = PlayerStyle::ALL.iter().copied().filter(|style| style.unlock_cost() == 0).collect();
+        self.save_unlocked_skins();
+        if !self.unlocked_skins.contains(&self.settings.player_style) {
+            self.settings.player_style = PlayerStyle::Duck;
+        }
+
+        self.achievements.clear();
+        self.storage.remove(ACHIEVEMENTS_FILE);
+    }
+
+    // 选择皮肤：已解锁的直接换上；没解锁的金币够就买下并换上，不够则什么也不做
+    fn select_player_style(&mut self, style: PlayerStyle) {
+        if !self.unlocked_skins.contains(&style) {
+            if self.total_coins < style.unlock_cost() {
+                return;
+            }
+            self.total_coins -= style.unlock_cost();
+            self.unlocked_skins.push(style);
+            self.storage.write(COINS_FILE, &self.total_coins.to_string());
+            self.save_unlocked_skins();
+        }
+        self.settings.player_style = style;
+    }
+
+    fn render_obstacle_menu(&mut self, ctx: &mut BTerm) {
+        ctx.print_centered_at(center_x(), 12, self.tr(Text::ObstacleDistance));
+        ctx.print_centered_at(center_x(), 14, fill(self.tr(Text::CurrentSpaces), &[&self.settings.obstacle_distance]));
+        ctx.print_centered_at(center_x(), 16, self.tr(Text::AdjustHint));
+        ctx.print_centered_at(center_x(), 18, self.tr(Text::Back));
+
+        self.render_obstacle_preview(ctx);
+
+        // 管道每帧移动 speed 格、每隔 obstacle_distance 格出现一根，按当前帧率估算每分钟的管道数
+        let speed = DEFAULT_PARAMETERS.obstacle_speed * self.settings.difficulty.speed_factor();
+        let pipes_per_minute = speed / self.settings.obstacle_distance as f32 * smoothed_fps(ctx) * 60.0;
+        let intensity = match pipes_per_minute as i32 {
+            0..=29 => Text::Relaxed,
+            30..=44 => Text::Moderate,
+            _ => Text::Frantic,
+        };
+        ctx.print_centered_at(center_x(), 33, fill(self.tr(Text::Pace), &[&format!("{:.0}", pipes_per_minute), &self.tr(intensity)]));
+    }
+
+    // 按当前难度和玩法算出前 gap_curve_points 分里缺口高度的变化，每分一列画成小折线；
+    // 每格分上下半格，纵向精度是行数的两倍。每帧重新计算，改了设置马上就能看到
+    fn render_gap_curve(&self, ctx: &mut BTerm, y: i32) {
+        let points = DEFAULT_PARAMETERS.gap_curve_points;
+        let rows = DEFAULT_PARAMETERS.gap_curve_rows;
+        let mut flags = RunFlags::capture(&self.settings);
+        if flags.difficulty == Difficulty::Adaptive {
+            flags.adaptive_level = self.adaptive.level;
+        }
+        // 和 curve_score、Obstacle::reset 的算法一致
+        let sizes: Vec<i32> = (0..=points)
+            .map(|score| {
+                let curve_score = if flags.mode == RunMode::Zen { 0 } else { score / flags.difficulty.score_multiplier() };
+                i32::max(DEFAULT_PARAMETERS.gap_min, DEFAULT_PARAMETERS.gap_size(curve_score) + flags.gap_adjust())
+            })
+            .collect();
+        let low = DEFAULT_PARAMETERS.gap_min;
+        let high = sizes.iter().copied().max().unwrap_or(low).max(DEFAULT_PARAMETERS.gap_start);
+        let levels = rows * 2 - 1;
+
+        ctx.print_centered_at(center_x(), y, fill(self.tr(Text::GapCurve), &[&points]));
+        let left = center_x() - points / 2;
+        let top = y + 2;
+        ctx.print_color_right(left - 1, top, WHITE, BLACK, high.to_string());
+        ctx.print_color_right(left - 1, top + rows - 1, WHITE, BLACK, low.to_string());
+        for (i, size) in sizes.iter().enumerate() {
+            let level = ((size - low) as f32 / (high - low).max(1) as f32 * levels as f32).round() as i32;
+            let row = top + rows - 1 - level / 2;
+            let glyph = if level % 2 == 1 { '▀' } else { '▄' };
+            ctx.set(left + i as i32, row, CYAN, BLACK, to_cp437(glyph));
+        }
+    }
+
+    // 在菜单里用缩小的画面预览当前间距和速度下管道出现的节奏，不影响真正的游戏
+    fn render_obstacle_preview(&mut self, ctx: &mut BTerm) {
+        let viewport = Viewport {
+            x: 36,
+            y: 21,
+            width: 48,
+            height: 10,
+            scale_x: 48.0 / DEFAULT_PARAMETERS.screen_width as f32,
+            scale_y: 10.0 / ground_top() as f32,
+            camera_offset_y: 0,
+        };
+
+        let speed = DEFAULT_PARAMETERS.obstacle_speed * self.settings.difficulty.speed_factor();
+        self.preview_distance += speed;
+        if self.preview_distance > self.settings.obstacle_distance as f32 {
+            self.preview_obstacles.push(Obstacle::new(DEFAULT_PARAMETERS.screen_width, 0, 0, &mut self.preview_rng));
+            self.preview_distance = 0.0;
+        }
+        self.preview_obstacles.retain(|obstacle| !obstacle.is_offscreen());
+
+        ctx.draw_hollow_box(viewport.x - 1, viewport.y - 1, viewport.width + 1, viewport.height + 1, WHITE, BLACK);
+        for obstacle in &mut self.preview_obstacles {
+            obstacle.update(speed);
+            obstacle.render(ctx, &viewport, &self.settings.theme, 0.0);
+        }
+    }
+
+    fn render_leaderboard_menu(&mut self, ctx: &mut BTerm) {
+        let board = match self.leaderboard_view {
+            LeaderboardKind::Casual => &self.casual_board,
+            LeaderboardKind::Hardcore => &self.hardcore_board,
+            LeaderboardKind::CasualTime => &self.casual_time_board,
+            LeaderboardKind::HardcoreTime => &self.hardcore_time_board,
+            LeaderboardKind::Zen => &self.zen_board,
+        };
+
+        ctx.print_color_centered_at(center_x(), 12, WHITE, BLACK, format!("< {} >", self.tr(board.kind.label())));
+
+        if board.entries.is_empty() {
+            ctx.print_color_centered_at(center_x(), 15, WHITE, RGBA::from_u8(0,0,0,0), self.tr(Text::NoScores));
+        }
+        for (i, entry) in board.entries.iter().enumerate() {
+            let difficulty = entry.difficulty.map_or("-", |difficulty| self.tr(difficulty.label()));
+            let line = if board.kind.ranks_by_time() {
+                format!("{:>2}. {:>5}  ({:>3} {}, {:<6})", i + 1, format_time(entry.time_ms), entry.pipes, self.tr(Text::PipesUnit), difficulty)
+            } else {
+                format!("{:>2}. {:>5}  ({:>3} {}, {:<6})", i + 1, entry.score, entry.pipes, self.tr(Text::PipesUnit), difficulty)
+            };
+            ctx.print_color_centered_at(center_x(), 15 + i, WHITE, RGBA::from_u8(0,0,0,0), line);
+        }
+
+        ctx.print_color_centered_at(center_x(), 27, WHITE, RGBA::from_u8(0,0,0,0), self.tr(Text::SwitchBoardHint));
+        ctx.print_color_centered_at(center_x(), 29, YELLOW, RGBA::from_u8(0,0,0,0), self.tr(Text::Back));
+    }
+
+    fn render_achievements_menu(&mut self, ctx: &mut BTerm) {
+        ctx.print_color_centered_at(
+            center_x(),
+            12,
+            WHITE,
+            BLACK,
+            fill(self.tr(Text::AchievementsCount), &[&self.achievements.len(), &Achievement::ALL.len()]),
+        );
+
+        // 已解锁的用绿色，未解锁的灰显
+        for (i, achievement) in Achievement::ALL.iter().enumerate() {
+            let (mark, color) = if self.achievements.contains(achievement) {
+                ("[x]", GREEN)
+            } else {
+                ("[ ]", DARK_GRAY)
+            };
+            let line = format!("{} {} - {}", mark, self.tr(achievement.name()), self.tr(achievement.description()));
+            ctx.print_color_centered_at(center_x(), 15 + i * 2, color, RGBA::from_u8(0,0,0,0), line);
+        }
+
+        ctx.print_color_centered_at(center_x(), 31, YELLOW, RGBA::from_u8(0,0,0,0), self.tr(Text::Back));
+    }
+
+    // 设置菜单的各项文字，最后两项固定为 Reset Progress 和 Back
+    fn option_labels(&self) -> Vec<String> {
+        let label = |key: Text, value: &str| format!("{}: < {} >", self.tr(key), value);
+        let toggle = |value: bool, on: Text, off: Text| self.tr(if value { on } else { off });
+        vec![
+            label(Text::MusicVolume, &self.settings.music_volume.to_string()),
+            label(Text::Lives, &self.settings.starting_lives.to_string()),
+            label(Text::FlapCooldown, &format!("{} ms", self.settings.flap_cooldown_ms)),
+            label(Text::PipeStyle, self.tr(self.settings.theme.pipe_glyphs.label())),
+            match self.settings.start_grace_ms {
+                Some(ms) => label(Text::StartGrace, &format!("{:.1}s", ms as f32 / 1000.0)),
+                None => label(Text::StartGrace, &format!("{} ({:.1}s)", self.tr(Text::Auto), self.settings.difficulty.grace_ms() as f32 / 1000.0)),
+            },
+            label(Text::BackgroundScroll, self.tr(self.settings.scroll_direction.label())),
+            label(Text::Gravity, self.tr(self.settings.gravity_curve.label())),
+            label(Text::UpcomingPipes, toggle(self.settings.ghost_obstacles, Text::Shown, Text::Hidden)),
+            label(Text::HighlightColor, self.tr(self.settings.theme.menu_highlight.label())),
+            label(Text::MenuTextColor, self.tr(self.settings.theme.menu_text.label())),
+            label(Text::SpeedTrails, toggle(self.settings.speed_trails, Text::On, Text::Off)),
+            label(Text::ReducedMotion, toggle(self.settings.reduced_motion, Text::On, Text::Off)),
+            label(Text::Scoring, self.tr(self.settings.scoring.label())),
+            label(Text::GapFeathering, toggle(self.settings.theme.gap_feather, Text::On, Text::Off)),
+            label(Text::FlapAssist, self.tr(self.settings.flap_assist.label())),
+            label(Text::NearPipeSlowdown, toggle(self.settings.near_pipe_slowdown, Text::On, Text::Off)),
+            match self.settings.hud_panel_opacity {
+                0 => label(Text::HudPanel, self.tr(Text::Off)),
+                opacity => label(Text::HudPanel, &format!("{}%", opacity * 10)),
+            },
+            label(Text::Flap, toggle(self.settings.classic_flap, Text::Classic, Text::HoldToClimb)),
+            label(Text::SpriteScaling, toggle(self.settings.smooth_sprites, Text::Smooth, Text::PixelArt)),
+            label(Text::IdealPath, toggle(self.settings.ideal_path, Text::Shown, Text::Hidden)),
+            label(Text::Language, self.settings.language.name()),
+            label(Text::PipeSpacing, toggle(self.settings.spacing_jitter, Text::Varied, Text::Fixed)),
+            label(Text::PassedPipes, self.settings.theme.passed_pipe.map_or(self.tr(Text::Off), |color| self.tr(color.label()))),
+            label(Text::BackgroundBrightness, &format!("{}%", self.settings.background_brightness * 10)),
+            label(Text::DimSprites, toggle(self.settings.dim_sprites, Text::On, Text::Off)),
+            label(Text::SafeZone, toggle(self.settings.safe_zone, Text::Shown, Text::Hidden)),
+            label(Text::Foreground, toggle(self.settings.foreground, Text::Shown, Text::Hidden)),
+            label(Text::QuickRestart, self.settings.keys.restart.map_or(self.tr(Text::Off), |key| self.key_name(key))),
+            label(Text::SoundVolume, &self.settings.sound_volume.to_string()),
+            label(Text::FlapKey, self.key_name(self.settings.keys.flap)),
+            label(Text::FastFallKey, self.key_name(self.settings.keys.fast_fall)),
+            self.tr(if self.menu_state.confirm_reset { Text::ReallyReset } else { Text::ResetProgress }).to_string(),
+            self.tr(Text::Back).to_string(),
+        ]
+    }
+
+    // 左右键调整设置菜单中当前选中的一项
+    fn adjust_option(&mut self, delta: i32) {
+        match self.menu_state.selected_option {
+            0 => self.settings.music_volume = (self.settings.music_volume + delta).clamp(0, 10),
+            1 => self.settings.starting_lives = (self.settings.starting_lives + delta).clamp(1, 5),
+            2 => self.settings.flap_cooldown_ms = (self.settings.flap_cooldown_ms + delta * 50).clamp(0, 500),
+            3 => self.settings.theme.pipe_glyphs = self.settings.theme.pipe_glyphs.cycle(delta),
+            // Auto 在最左边，往右每格加 0.5 秒，最多 3 秒
+            4 => {
+                let current = self.settings.start_grace_ms.map_or(-1, |ms| ms / 500);
+                let next = (current + delta).clamp(-1, 6);
+                self.settings.start_grace_ms = if next < 0 { None } else { Some(next * 500) };
+            }
+            5 => self.settings.scroll_direction = self.settings.scroll_direction.cycle(delta),
+            6 => self.settings.gravity_curve = self.settings.gravity_curve.cycle(delta),
+            7 => self.settings.ghost_obstacles = !self.settings.ghost_obstacles,
+            8 => self.settings.theme.menu_highlight = self.settings.theme.menu_highlight.cycle(delta),
+            9 => self.settings.theme.menu_text = self.settings.theme.menu_text.cycle(delta),
+            10 => self.settings.speed_trails = !self.settings.speed_trails,
+            11 => self.settings.reduced_motion = !self.settings.reduced_motion,
+            12 => self.settings.scoring = self.settings.scoring.cycle(delta),
+            13 => self.settings.theme.gap_feather = !self.settings.theme.gap_feather,
+            14 => self.settings.flap_assist = self.settings.flap_assist.cycle(delta),
+            15 => self.settings.near_pipe_slowdown = !self.settings.near_pipe_slowdown,
+            16 => self.settings.hud_panel_opacity = (self.settings.hud_panel_opacity + delta).clamp(0, 10),
+            17 => self.settings.classic_flap = !self.settings.classic_flap,
+            18 => self.settings.smooth_sprites = !self.settings.smooth_sprites,
+            19 => self.settings.ideal_path = !self.settings.ideal_path,
+            20 => self.settings.language = self.settings.language.cycle(delta),
+            21 => self.settings.spacing_jitter = !self.settings.spacing_jitter,
+            // Off 在最左边，往右依次是各个主题色
+            22 => {
+                let colors = ThemeColor::ALL.len() as i32;
+                let current = self.settings.theme.passed_pipe
+                    .map_or(-1, |color| ThemeColor::ALL.iter().position(|c| *c == color).unwrap_or(0) as i32);
+                let next = (current + 1 + delta).rem_euclid(colors + 1) - 1;
+                self.settings.theme.passed_pipe = if next < 0 { None } else { Some(ThemeColor::ALL[next as usize]) };
+            }
+            23 => {
+                self.settings.background_brightness = (self.settings.background_brightness + delta).clamp(3, 10);
+                self.storage.write(BRIGHTNESS_FILE, &self.settings.background_brightness.to_string());
+            }
+            24 => self.settings.dim_sprites = !self.settings.dim_sprites,
+            25 => self.settings.safe_zone = !self.settings.safe_zone,
+            26 => self.settings.foreground = !self.settings.foreground,
+            27 => {
+                self.settings.keys.restart = match self.settings.keys.restart {
+                    Some(_) => None,
+                    None => Some(VirtualKeyCode::R),
+                };
+            }
+            28 => self.settings.sound_volume = (self.settings.sound_volume + delta).clamp(0, 10),
+            29 => {
+                let keys = &mut self.settings.keys;
+                keys.flap = KeyBindings::cycle_key(keys.flap, &KeyBindings::FLAP_KEYS, keys.fast_fall, delta);
+            }
+            30 => {
+                let keys = &mut self.settings.keys;
+                keys.fast_fall = KeyBindings::cycle_key(keys.fast_fall, &KeyBindings::FAST_FALL_KEYS, keys.flap, delta);
+            }
+            _ => {}
+        }
+    }
+
+    fn render_options_menu(&mut self, ctx: &mut BTerm) {
+        let options = self.option_labels();
+
+        ctx.print_color_centered_at(center_x(), 12, WHITE, BLACK, self.tr(Text::Options));
+
+        for (i, option) in options.iter().enumerate() {
+            let color = self.settings.theme.menu_item_color(i as i32 == self.menu_state.selected_option);
+
+            ctx.print_color_centered_at(center_x(), 14 + i, color, RGBA::from_u8(0,0,0,0), option);
+        }
+        ctx.print_color_centered_at(center_x(), 15 + options.len(), WHITE, RGBA::from_u8(0,0,0,0), self.tr(Text::AdjustHint));
+    }
+
+    fn handle_menu_input(&mut self, ctx: &mut BTerm) {
+        // 确认退出时只有 Y 或再按一次回车才退出，其他任何键都取消
+        if self.menu_state.confirm_quit {
+            if let Some(key) = ctx.key {
+                match key {
+                    VirtualKeyCode::Y | VirtualKeyCode::Return => ctx.quitting = true,
+                    _ => self.menu_state.confirm_quit = false,
+                }
+            }
+            return;
+        }
+
+        // 确认重置进度时同样只认 Y 或回车
+        if self.menu_state.confirm_reset {
+            if let Some(key) = ctx.key {
+                if matches!(key, VirtualKeyCode::Y | VirtualKeyCode::Return) {
+                    self.reset_progress();
+                }
+                self.menu_state.confirm_reset = false;
+            }
+            return;
+        }
+
+        // 确认删除存档时也一样
+        if self.menu_state.confirm_delete {
+            if let Some(key) = ctx.key {
+                if matches!(key, VirtualKeyCode::Y | VirtualKeyCode::Return) {
+                    self.delete_selected_profile();
+                }
+                self.menu_state.confirm_delete = false;
+            }
+            return;
+        }
+
+        if let Some(key) = ctx.key {
+            match key {
+                VirtualKeyCode::Up if self.menu_state.selected_option > 0 => {
+                    self.menu_state.selected_option -= 1;
+                }
+                VirtualKeyCode::Down => {
+                    let max_options = match self.menu_state.current_menu {
+                        MainMenuOption::Main => 12,
+                        MainMenuOption::Background => 3,
+                        MainMenuOption::Player => 3,
+                        MainMenuOption::Obstacle => 1,
+                        MainMenuOption::Leaderboard => 0,
+                        MainMenuOption::Options => self.option_labels().len() as i32 - 1,
+                        MainMenuOption::ShareCode => 0,
+                        MainMenuOption::Achievements => 0,
+                        MainMenuOption::Profiles => self.profiles.len() as i32 + 1,
+                        MainMenuOption::NewProfile => 0,
+                    };
+
+                    if self.menu_state.selected_option < max_options {
+                        self.menu_state.selected_option += 1;
+                    }
+                }
+                VirtualKeyCode::Return => {
+                    match self.menu_state.current_menu {
+                        MainMenuOption::Main => {
+                            match self.menu_state.selected_option {
+                                0 => {
+                                    self.hot_seat = None;
+                                    self.restart(); // 开始游戏
+                                }
+                                1 => self.open_submenu(MainMenuOption::Background, 1),
+                                2 => self.open_submenu(MainMenuOption::Player, 2),
+                                3 => self.open_submenu(MainMenuOption::Obstacle, 3),
+                                4 => self.settings.difficulty = self.settings.difficulty.next(),
+                                5 => self.settings.run_mode = self.settings.run_mode.cycle(1),
+                                6 => self.open_submenu(MainMenuOption::Leaderboard, 6),
+                                7 => self.open_submenu(MainMenuOption::Achievements, 7),
+                                8 => self.open_submenu(MainMenuOption::Options, 8),
+                                9 => self.start_hot_seat(),
+                                10 => {
+                                    self.share_code_input.clear();
+                                    self.share_code_error = None;
+                                    self.open_submenu(MainMenuOption::ShareCode, 10);
+                                }
+                                11 => self.open_profiles_menu(),
+                                12 => self.menu_state.confirm_quit = true, // 退出前先确认
+                                _ => {}
+                            }
+                        }
+                        MainMenuOption::Background => {
+                            match self.menu_state.selected_option {
+                                0 => self.settings.background_style = BackgroundStyle::Stars,
+                                1 => self.settings.background_style = BackgroundStyle::Clouds,
+                                2 => self.settings.background_style = BackgroundStyle::Mountains,
+                                3 => self.close_submenu(),
+                                _ => {}
+                            }
+                        }
+                        MainMenuOption::Player => {
+                            match self.menu_state.selected_option {
+                                0 => self.select_player_style(PlayerStyle::Dragon),
+                                1 => self.select_player_style(PlayerStyle::Bird),
+                                2 => self.select_player_style(PlayerStyle::Duck),
+                                3 => self.close_submenu(),
+                                _ => {}
+                            }
+                        }
+                        MainMenuOption::Obstacle => {
+                            if self.menu_state.selected_option == 1 {
+                                self.close_submenu();
+                            }
+                        }
+                        MainMenuOption::Leaderboard => self.close_submenu(),
+                        MainMenuOption::Options => {
+                            let back = self.option_labels().len() as i32 - 1;
+                            if self.menu_state.selected_option == back {
+                                self.close_submenu();
+                            } else if self.menu_state.selected_option == back - 1 {
+                                self.menu_state.confirm_reset = true; // 重置前先确认
+                            }
+                        }
+                        MainMenuOption::ShareCode => self.submit_share_code(),
+                        MainMenuOption::Achievements => self.close_submenu(),
+                        MainMenuOption::Profiles => {
+                            let selected = self.menu_state.selected_option as usize;
+                            let count = self.profiles.len();
+                            if let Some(name) = self.profiles.get(selected).cloned() {
+                                if name != self.storage.profile {
+                                    self.switch_profile(&name);
+                                }
+                                self.close_submenu();
+                            } else if selected == count {
+                                self.profile_name_input.clear();
+                                self.profile_error = None;
+                                self.menu_state.current_menu = MainMenuOption::NewProfile;
+                                self.menu_state.selected_option = 0;
+                            } else {
+                                self.close_submenu();
+                            }
+                        }
+                        MainMenuOption::NewProfile => self.submit_profile_name(),
+                    }
+                }
+                VirtualKeyCode::Left => {
+                    match (&self.menu_state.current_menu, self.menu_state.selected_option) {
+                        (MainMenuOption::Main, 4) => self.settings.difficulty = self.settings.difficulty.prev(),
+                        (MainMenuOption::Main, 5) => self.settings.run_mode = self.settings.run_mode.cycle(-1),
+                        (MainMenuOption::Obstacle, 0) => {
+                            self.settings.obstacle_distance = i32::max(40, self.settings.obstacle_distance - 5);
+                        }
+                        (MainMenuOption::Leaderboard, _) => self.leaderboard_view = self.leaderboard_view.cycle(-1),
+                        (MainMenuOption::Options, _) => self.adjust_option(-1),
+                        _ => {}
+                    }
+                }
+                VirtualKeyCode::Right => {
+                    match (&self.menu_state.current_menu, self.menu_state.selected_option) {
+                        (MainMenuOption::Main, 4) => self.settings.difficulty = self.settings.difficulty.next(),
+                        (MainMenuOption::Main, 5) => self.settings.run_mode = self.settings.run_mode.cycle(1),
+                        (MainMenuOption::Obstacle, 0) => {
+                            self.settings.obstacle_distance = i32::min(60, self.settings.obstacle_distance + 5);
+                        }
+                        (MainMenuOption::Leaderboard, _) => self.leaderboard_view = self.leaderboard_view.cycle(1),
+                        (MainMenuOption::Options, _) => self.adjust_option(1),
+                        _ => {}
+                    }
+                }
+                // 主菜单下数字键直接进入对应子菜单
+                VirtualKeyCode::Key1 if self.menu_state.current_menu == MainMenuOption::Main => {
+                    self.open_submenu(MainMenuOption::Background, 1);
+                }
+                VirtualKeyCode::Key2 if self.menu_state.current_menu == MainMenuOption::Main => {
+                    self.open_submenu(MainMenuOption::Player, 2);
+                }
+                VirtualKeyCode::Key3 if self.menu_state.current_menu == MainMenuOption::Main => {
+                    self.open_submenu(MainMenuOption::Obstacle, 3);
+                }
+                VirtualKeyCode::D if self.menu_state.current_menu == MainMenuOption::Profiles => {
+                    match self.profiles.get(self.menu_state.selected_option as usize) {
+                        Some(name) if name == DEFAULT_PROFILE => {
+                            self.profile_error = Some(self.tr(Text::CannotDeleteDefault).to_string());
+                        }
+                        Some(_) => self.menu_state.confirm_delete = true, // 删除前先确认
+                        None => {}
+                    }
+                }
+                VirtualKeyCode::Escape if self.menu_state.current_menu == MainMenuOption::NewProfile => {
+                    self.profile_error = None;
+                    self.return_to_profiles_menu();
+                }
+                VirtualKeyCode::Escape if self.menu_state.current_menu != MainMenuOption::Main => {
+                    self.close_submenu();
+                }
+                _ if self.menu_state.current_menu == MainMenuOption::ShareCode => {
+                    self.share_code_input.handle_key(key, ctx.shift);
+                }
+                _ if self.menu_state.current_menu == MainMenuOption::NewProfile => {
+                    self.profile_name_input.handle_key(key, ctx.shift);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn render_share_code_menu(&mut self, ctx: &mut BTerm) {
+        ctx.print_color_centered_at(center_x(), 12, WHITE, BLACK, self.tr(Text::EnterShareCode));
+        self.share_code_input.render(ctx, 15, YELLOW);
+        if let Some(error) = &self.share_code_error {
+            ctx.print_color_centered_at(center_x(), 17, RED, BLACK, error);
+        }
+        ctx.print_color_centered_at(center_x(), 19, WHITE, RGBA::from_u8(0,0,0,0), self.tr(Text::ShareCodeHint));
+    }
+
+    // 分享码有效时套用其中的设置并开始这一局，无效时只显示错误
+    fn submit_share_code(&mut self) {
+        match ShareCode::decode(self.share_code_input.text()) {
+            Ok(code) => {
+                self.settings.difficulty = code.difficulty;
+                self.settings.run_mode = code.run_mode;
+                self.settings.obstacle_distance = code.obstacle_distance;
+                self.settings.spacing_jitter = code.spacing_jitter;
+                self.hot_seat = None;
+                match code.adaptive_level {
+                    Some(level) => self.restart_at_level(code.seed, level),
+                    None => self.restart_with_seed(code.seed),
+                }
+            }
+            Err(error) => self.share_code_error = Some(error),
+        }
+    }
+
+    // 进入子菜单时光标回到第一项，并记下这个子菜单在主菜单里的位置；
+    // 数字键和启动参数不经过光标打开子菜单，所以不能直接用当前的光标位置
+    fn open_submenu(&mut self, menu: MainMenuOption, parent_option: i32) {
+        self.menu_state.parent_option = parent_option;
+        self.menu_state.current_menu = menu;
+        self.menu_state.selected_option = 0;
+    }
+
+    // Back 和 Escape 都回到主菜单中进入子菜单前的位置
+    fn close_submenu(&mut self) {
+        self.menu_state.current_menu = MainMenuOption::Main;
+        self.menu_state.selected_option = self.menu_state.parent_option;
+    }
+
+    fn render_background(&self, ctx: &mut BTerm) {
+        match self.settings.background_style {
+            BackgroundStyle::Stars => self.render_stars_background(ctx),
+            BackgroundStyle::Clouds => self.render_clouds_background(ctx),
+            BackgroundStyle::Mountains => self.render_mountains_background(ctx),
+        }
+    }
+
+    fn render_stars_background(&self, ctx: &mut BTerm) {
+        self.render_looping_background(ctx, self.texture.background_image(&BackgroundStyle::Stars, self.background_frame));
+    }
+
+    fn render_clouds_background(&self, ctx: &mut BTerm) {
+        self.render_looping_background(ctx, self.texture.background_image(&BackgroundStyle::Clouds, self.background_frame));
+    }
+
+    fn render_mountains_background(&self, ctx: &mut BTerm) {
+        self.render_looping_background(ctx, self.texture.background_image(&BackgroundStyle::Mountains, self.background_frame));
+    }
+
+    fn render_looping_background(&self, ctx: &mut BTerm, background: &image::DynamicImage) {
+        let tint = self.combo_tint();
+        let pulse = self.combo_pulse_color();
+        let chaos = self.chaos_effects();
+        // 光点跟着背景一起滚动，所以按背景上的位置而不是屏幕位置来挑格子
+        let offset = self.run.background_offset as i32;
+        for y in 0..DEFAULT_PARAMETERS.screen_height {
+            for x in 0..DEFAULT_PARAMETERS.screen_width {
+                let mut color = self.sample_background(background, x, y);
+                if chaos.tint > 0.0 {
+                    color = color.lerp(RGB::named(RED), chaos.tint);
+                }
+                if tint > 0.0 {
+                    color = color.lerp(pulse, tint);
+                }
+                let (world_x, world_y) = match self.settings.scroll_direction {
+                    ScrollDirection::Vertical => (x, (y + offset).rem_euclid(DEFAULT_PARAMETERS.screen_height)),
+                    _ => ((x + offset).rem_euclid(DEFAULT_PARAMETERS.screen_width), y),
+                };
+                if chaos.detail > 0.0 && cell_noise(world_x, world_y) < chaos.detail {
+                    ctx.set(x, y, color.lerp(RGB::named(WHITE), 0.5), color, to_cp437('.'));
+                } else {
+                    ctx.set(x, y, BLACK,color, to_cp437(' '));
+                }
+            }
+        }
+    }
+
+    fn background_image(&self) -> &image::DynamicImage {
+        self.texture.background_image(&self.settings.background_style, self.background_frame)
+    }
+
+    // 屏幕上 (x, y) 处的背景颜色
+    fn sample_background(&self, background: &image::DynamicImage, x: i32, y: i32) -> RGB {
+        self.background_color(background, x, y, self.run.background_offset)
+    }
+
+    // 背景滚动到 offset 时 (x, y) 处的颜色
+    fn background_color(&self, background: &image::DynamicImage, x: i32, y: i32, offset: f32) -> RGB {
+        let width = background.width() as i32;
+        let height = background.height() as i32;
+        let offset = offset as i32;
+
+        // 用 rem_euclid 而不是 %，偏移量或坐标为负时下标也一定落在图片范围内
+        let (bg_x, bg_y) = match self.settings.scroll_direction {
+            ScrollDirection::Vertical => (x.rem_euclid(width), (y + offset).rem_euclid(height)),
+            _ => ((x + offset).rem_euclid(width), y.rem_euclid(height)),
+        };
+
+        let pixel = background.get_pixel(bg_x as u32, bg_y as u32);
+        RGB::from_u8(pixel[0], pixel[1], pixel[2]) * self.background_brightness()
+    }
+
+    // 背景颜色的亮度倍率；所有背景取色都经过 background_color，面板、光晕和截图也一起变暗
+    fn background_brightness(&self) -> f32 {
+        self.settings.background_brightness as f32 / 10.0
+    }
+
+    // 玩家贴图的亮度倍率，没开 dim_sprites 时保持原样
+    fn sprite_brightness(&self) -> f32 {
+        if self.settings.dim_sprites { self.background_brightness() } else { 1.0 }
+    }
+
+    // HUD 背后横跨整个屏幕的底条，在背景上压暗，文字改成白色才不会和背景混在一起
+    fn render_hud_panel(&self, ctx: &mut BTerm, rows: i32) {
+        let opacity = self.settings.hud_panel_opacity as f32 / 10.0;
+        if opacity <= 0.0 {
+            return;
+        }
+        let background = self.background_image();
+        for y in 0..rows {
+            for x in 0..DEFAULT_PARAMETERS.screen_width {
+                let color = self.sample_background(background, x, y).lerp(RGB::named(BLACK), opacity);
+                ctx.set(x, y, WHITE, color, to_cp437(' '));
+            }
+        }
+    }
+
+    // 提示画在游戏用的控制台 1 上，但按窗口的实际大小重新设定格数，每格 tile_pixels 像素：
+    // 不开 --letterbox 时控制台 0 固定 120x80 拉伸到窗口里，在小窗口上字会小得看不清。
+    // 控制台 1 的尺寸、平移和裁剪每帧都由 begin_frame 重新设定，窗口变大后自动恢复
+    fn render_window_too_small(&self, ctx: &mut BTerm) {
+        let (min_width, min_height) = min_window_size();
+        let (window_width, window_height) = window_logical_size(ctx);
+        let width = u32::max(1, window_width / DEFAULT_PARAMETERS.tile_pixels);
+        let height = u32::max(1, window_height / DEFAULT_PARAMETERS.tile_pixels);
+        ctx.set_active_console(1);
+        ctx.set_char_size(width, height);
+        ctx.set_offset(0.0, 0.0);
+        ctx.set_clipping(None);
+        ctx.cls();
+        let center = width as i32 / 2;
+        let middle = height as i32 / 2;
+        ctx.print_color_centered_at(center, middle - 1, YELLOW, DEFAULT_PARAMETERS.letterbox_color, self.tr(Text::WindowTooSmall));
+        ctx.print_color_centered_at(center, middle + 1, WHITE, DEFAULT_PARAMETERS.letterbox_color, fill(self.tr(Text::WindowMinSize), &[&min_width, &min_height]));
+    }
+
+    // 在背景上叠一块暗色面板，opacity 为 0 时完全透明，为 1 时纯黑
+    fn render_panel(&self, ctx: &mut BTerm, x: i32, y: i32, width: i32, height: i32, opacity: f32) {
+        let background = self.background_image();
+        for py in y..y + height {
+            for px in x..x + width {
+                let color = self.sample_background(background, px, py).lerp(RGB::named(BLACK), opacity);
+                ctx.set(px, py, BLACK, color, to_cp437(' '));
+            }
+        }
+    }
+}
+
+impl Viewport {
+    // 整个屏幕，不缩放
+    fn full(camera_offset_y: i32) -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            width: DEFAULT_PARAMETERS.screen_width,
+            height: DEFAULT_PARAMETERS.screen_height,
+            scale_x: 1.0,
+            scale_y: 1.0,
+            camera_offset_y,
+        }
+    }
+
+    fn to_screen(&self, world_x: f32, world_y: i32) -> Option<(i32, i32)> {
+        let local_x = (world_x * self.scale_x) as i32;
+        let local_y = ((world_y - self.camera_offset_y) as f32 * self.scale_y) as i32;
+        if local_x < 0 || local_x >= self.width || local_y < 0 || local_y >= self.height {
+            return None;
+        }
+        Some((self.x + local_x, self.y + local_y))
+    }
+}
+
+// 本帧经过的时间（毫秒），异常值（0、NaN、卡顿后的超大值）会被限制到合理范围
+fn frame_time_ms(ctx: &BTerm) -> f32 {
+    let ms = ctx.frame_time_ms;
+    if ms.is_nan() {
+        return DEFAULT_PARAMETERS.min_frame_time_ms;
+    }
+    ms.clamp(DEFAULT_PARAMETERS.min_frame_time_ms, DEFAULT_PARAMETERS.max_frame_time_ms)
+}
+
+// bracket-lib 每秒统计一次的帧率，比单帧耗时稳定；刚启动还没统计出来时按 60 算
+fn smoothed_fps(ctx: &BTerm) -> f32 {
+    if ctx.fps > 0.0 { ctx.fps } else { 60.0 }
+}
+
+// 玩家框里 (x, y) 格的颜色。贴图可以是任意分辨率，按比例缩放到 player_width x player_height：
+// 默认取最近的源像素，像素画保持锐利；平滑模式在格子中心做双线性插值，半透明的边缘按一半透明度取舍。
+// 镜像时按翻转后的 x 取样，直接读原图即可，不需要额外缓存
+fn sample_sprite(image: &image::DynamicImage, x: i32, y: i32, mirrored: bool, smooth: bool) -> image::Rgba<u8> {
+    let (width, height) = image.dimensions();
+    let x = if mirrored { DEFAULT_PARAMETERS.player_width - 1 - x } else { x };
+    if !smooth {
+        let source_x = x as u32 * width / DEFAULT_PARAMETERS.player_width as u32;
+        let source_y = y as u32 * height / DEFAULT_PARAMETERS.player_height as u32;
+        return image.get_pixel(source_x, source_y);
+    }
+    let source_x = (x as f32 + 0.5) * width as f32 / DEFAULT_PARAMETERS.player_width as f32 - 0.5;
+    let source_y = (y as f32 + 0.5) * height as f32 / DEFAULT_PARAMETERS.player_height as f32 - 0.5;
+    let pixel = image::imageops::interpolate_bilinear(
+        image,
+        source_x.clamp(0.0, (width - 1) as f32),
+        source_y.clamp(0.0, (height - 1) as f32),
+    );
+    match pixel {
+        Some(image::Rgba([r, g, b, alpha])) if alpha >= 128 => image::Rgba([r, g, b, 255]),
+        _ => image::Rgba([0, 0, 0, 0]),
+    }
+}
+
+// 把按行排列的整屏格子放大成图片，每格 scale x scale 像素
+fn cells_to_image(cells: &[(RGB, bool)], scale: u32) -> image::RgbaImage {
+    let width = DEFAULT_PARAMETERS.screen_width as u32;
+    let height = DEFAULT_PARAMETERS.screen_height as u32;
+    let pipe_line = RGB::named(RED);
+    image::RgbaImage::from_fn(width * scale, height * scale, |px, py| {
+        let (color, pipe) = cells[((py / scale) * width + px / scale) as usize];
+        // 管道格中间画一条竖线，对应屏幕上的 '|' 字符
+        let color = if pipe && px % scale == scale / 2 { pipe_line } else { color };
+        image::Rgba([(color.r * 255.0) as u8, (color.g * 255.0) as u8, (color.b * 255.0) as u8, 255])
+    })
+}
+
+// 没有引入剪贴板库，交给系统自带的剪贴板命令，依次尝试，全都不行时返回 false
+fn copy_to_clipboard(text: &str) -> bool {
+    let commands: &[(&str, &[&str])] = if cfg!(target_os = "windows") {
+        &[("clip", &[])]
+    } else if cfg!(target_os = "macos") {
+        &[("pbcopy", &[])]
+    } else {
+        &[("wl-copy", &[]), ("xclip", &["-selection", "clipboard"]), ("xsel", &["--clipboard", "--input"])]
+    };
+    commands.iter().any(|(program, args)| {
+        let Ok(mut child) = Command::new(program).args(*args).stdin(Stdio::piped()).spawn() else {
+            return false;
+        };
+        // 先关掉 stdin，命令读到结尾才会退出
+        let written = child.stdin.take().is_some_and(|mut stdin| stdin.write_all(text.as_bytes()).is_ok());
+        child.wait().is_ok_and(|status| status.success()) && written
+    })
+}
+
+// 毫秒数显示为 mm:ss
+fn format_time(ms: i32) -> String {
+    let seconds = ms.max(0) / 1000;
+    format!("{:02}:{:02}", seconds / 60, seconds % 60)
+}
+
+// 每个格子固定的伪随机数 0~1，同一个格子每帧都一样
+fn cell_noise(x: i32, y: i32) -> f32 {
+    let hash = (x.wrapping_mul(73_856_093) ^ y.wrapping_mul(19_349_663)).wrapping_mul(83_492_791);
+    (hash >> 8).rem_euclid(1000) as f32 / 1000.0
+}
+
+// 存在这个文件说明已经玩过教程
+const TUTORIAL_MARKER: &str = "played_before";
+
+// 累计金币和已解锁皮肤的存档
+const COINS_FILE: &str = "coins.txt";
+const SKINS_FILE: &str = "skins.txt";
+
+const ACHIEVEMENTS_FILE: &str = "achievements.txt";
+const BRIGHTNESS_FILE: &str = "brightness.txt";
+const LAST_SCORE_FILE: &str = "last_score.txt";
+const PROFILES_DIR: &str = "profiles";
+const DEFAULT_PROFILE: &str = "default";
+
+// 可选的前景贴图，和音乐一样运行时从当前目录读取，没有这个文件就不画前景
+const FOREGROUND_FILE: &str = "foreground.png";
+
+// 扇翅膀的音效，同样是可选的
+const FLAP_SOUND_FILE: &str = "sfx/flap.ogg";
+
+impl TextInput {
+    fn new(max_len: usize, charset: Charset) -> Self {
+        Self { text: String::new(), max_len, charset }
+    }
+
+    fn text(&self) -> &str {
+        &self.text
+    }
+
+    fn clear(&mut self) {
+        self.text.clear();
+    }
+
+    // 退格删掉最后一个字符；其余按键能转成字符且没到长度上限时追加
+    fn handle_key(&mut self, key: VirtualKeyCode, shift: bool) {
+        if key == VirtualKeyCode::Back {
+            self.text.pop();
+            return;
+        }
+        if let Some(c) = self.charset.key_to_char(key, shift).filter(|_| self.text.chars().count() < self.max_len) {
+            self.text.push(c);
+        }
+    }
+
+    // 居中画成 "> 内容_"，写满后不再画光标
+    fn render(&self, ctx: &mut BTerm, y: i32, color: (u8, u8, u8)) {
+        let caret = if self.text.chars().count() < self.max_len { "_" } else { "" };
+        ctx.print_color_centered_at(center_x(), y, color, BLACK, format!("> {}{}", self.text, caret));
+    }
+}
+
+impl Charset {
+    fn key_to_char(&self, key: VirtualKeyCode, shift: bool) -> Option<char> {
+        if key == VirtualKeyCode::Space {
+            return (*self == Charset::Name).then_some(' ');
+        }
+        let c = match key {
+            VirtualKeyCode::Key0 | VirtualKeyCode::Numpad0 => '0',
+            VirtualKeyCode::Key1 | VirtualKeyCode::Numpad1 => '1',
+            VirtualKeyCode::Key2 | VirtualKeyCode::Numpad2 => '2',
+            VirtualKeyCode::Key3 | VirtualKeyCode::Numpad3 => '3',
+            VirtualKeyCode::Key4 | VirtualKeyCode::Numpad4 => '4',
+            VirtualKeyCode::Key5 | VirtualKeyCode::Numpad5 => '5',
+            VirtualKeyCode::Key6 | VirtualKeyCode::Numpad6 => '6',
+            VirtualKeyCode::Key7 | VirtualKeyCode::Numpad7 => '7',
+            VirtualKeyCode::Key8 | VirtualKeyCode::Numpad8 => '8',
+            VirtualKeyCode::Key9 | VirtualKeyCode::Numpad9 => '9',
+            VirtualKeyCode::A => 'A',
+            VirtualKeyCode::B => 'B',
+            VirtualKeyCode::C => 'C',
+            VirtualKeyCode::D => 'D',
+            VirtualKeyCode::E => 'E',
+            VirtualKeyCode::F => 'F',
+            VirtualKeyCode::G => 'G',
+            VirtualKeyCode::H => 'H',
+            VirtualKeyCode::I => 'I',
+            VirtualKeyCode::J => 'J',
+            VirtualKeyCode::K => 'K',
+            VirtualKeyCode::L => 'L',
+            VirtualKeyCode::M => 'M',
+            VirtualKeyCode::N => 'N',
+            VirtualKeyCode::O => 'O',
+            VirtualKeyCode::P => 'P',
+            VirtualKeyCode::Q => 'Q',
+            VirtualKeyCode::R => 'R',
+            VirtualKeyCode::S => 'S',
+            VirtualKeyCode::T => 'T',
+            VirtualKeyCode::U => 'U',
+            VirtualKeyCode::V => 'V',
+            VirtualKeyCode::W => 'W',
+            VirtualKeyCode::X => 'X',
+            VirtualKeyCode::Y => 'Y',
+            VirtualKeyCode::Z => 'Z',
+            _ => return None,
+        };
+        if *self == Charset::Name && !shift {
+            return Some(c.to_ascii_lowercase());
+        }
+        Some(c)
+    }
+}
+
+// 游戏区的水平中心，居中文字以此为准（窗口可能比游戏区宽）
+fn center_x() -> i32 {
+    DEFAULT_PARAMETERS.screen_width / 2
+}
+
+// 每帧开始：底层控制台铺满边框色，游戏画在上层，并把上层平移到窗口中央。
+// 窗口比游戏区窄或矮时（例如竖屏），上层控制台按比例多分一些格子，整个游戏区等比缩小后放得下
+fn begin_frame(ctx: &mut BTerm) {
+    ctx.set_active_console(0);
+    ctx.cls_bg(DEFAULT_PARAMETERS.letterbox_color);
+    let (window_width, window_height) = ctx.get_char_size();
+    let fit = f32::min(
+        1.0,
+        f32::min(
+            window_width as f32 / DEFAULT_PARAMETERS.screen_width as f32,
+            window_height as f32 / DEFAULT_PARAMETERS.screen_height as f32,
+        ),
+    );
+    let width = (window_width as f32 / fit).round() as u32;
+    let height = (window_height as f32 / fit).round() as u32;
+
+    ctx.set_active_console(1);
+    ctx.set_char_size(width, height);
+    ctx.cls();
+    let margin_x = (width as i32 - DEFAULT_PARAMETERS.screen_width).max(0) / 2;
+    let margin_y = (height as i32 - DEFAULT_PARAMETERS.screen_height).max(0) / 2;
+    ctx.set_offset(margin_x as f32, -margin_y as f32); // OpenGL 的 y 轴向上
+    ctx.set_clipping(Some(Rect::with_size(0, 0, DEFAULT_PARAMETERS.screen_width, DEFAULT_PARAMETERS.screen_height)));
+}
+
+// 窗口能放下的最小尺寸（逻辑像素）：游戏区每格 min_cell_pixels 像素
+fn min_window_size() -> (u32, u32) {
+    (
+        DEFAULT_PARAMETERS.screen_width as u32 * DEFAULT_PARAMETERS.min_cell_pixels,
+        DEFAULT_PARAMETERS.screen_height as u32 * DEFAULT_PARAMETERS.min_cell_pixels,
+    )
+}
+
+// 窗口的逻辑尺寸：width_pixels 是物理像素，高分屏上要除以缩放比例
+fn window_logical_size(ctx: &BTerm) -> (u32, u32) {
+    let scale = INPUT.lock().get_scale_factor();
+    let scale = if scale > 0.0 { scale } else { 1.0 };
+    (
+        (ctx.width_pixels as f64 / scale) as u32,
+        (ctx.height_pixels as f64 / scale) as u32,
+    )
+}
+
+// 游戏区按比例缩小以后，窗口的宽或高只要有一边不够就算太小；按逻辑像素比较，和 min_window_size 的单位一致
+fn window_too_small(ctx: &BTerm) -> bool {
+    let (min_width, min_height) = min_window_size();
+    let (width, height) = window_logical_size(ctx);
+    width < min_width || height < min_height
+}
+
+// 地面顶部的 y 坐标，玩家碰到这里即死亡
+fn ground_top() -> i32 {
+    DEFAULT_PARAMETERS.screen_height - DEFAULT_PARAMETERS.ground_height
+}
+
+// 地面最上一行是草地，下面是泥土
+fn ground_color(y: i32) -> RGB {
+    if y == ground_top() {
+        RGB::from_u8(86, 170, 48)
+    } else {
+        RGB::from_u8(133, 94, 66)
+    }
+}
+
+impl Player {
+    fn new(x: i32, y: i32) -> Self {
+        Self {
+            x,
+            y,
+            velocity: 0.0,
+        }
+    }
+
+    // 开局位置：玩家中心落在屏幕高度的 player_start_fraction 处
+    fn start() -> Self {
+        let center_y = (DEFAULT_PARAMETERS.screen_height as f32 * DEFAULT_PARAMETERS.player_start_fraction) as i32;
+        Self::new(2, center_y - DEFAULT_PARAMETERS.player_height / 2)
+    }
+
+fn render(&self, ctx: &mut BTerm, image: &image::DynamicImage, camera_offset_y: i32, mirrored: bool, smooth: bool, brightness: f32) {
+    for y in 0..DEFAULT_PARAMETERS.player_height {
+        for x in 0..DEFAULT_PARAMETERS.player_width {
+            let screen_x = self.x + x;
+            let screen_y = self.y + y - camera_offset_y;
+
+            if screen_x < 0 || screen_x >= DEFAULT_PARAMETERS.screen_width ||
+               screen_y < 0 || screen_y >= DEFAULT_PARAMETERS.screen_height {
+                continue;
+            }
+
+            let pixel = sample_sprite(image, x, y, mirrored, smooth);
+            let alpha = pixel[3];
+
+            if alpha == 0 {
+                continue; // 透明像素不渲染
+            }
+
+            let color = RGB::from_u8(pixel[0], pixel[1], pixel[2]) * brightness;
+            ctx.set(screen_x, screen_y, BLACK, color, to_cp437(' '));
+        }
+    }
+}
+
+    fn gravity_to_move(&mut self, physics: &PlayerPhysics) {
+        self.velocity = physics.curve.accelerate(self.velocity, physics);
+        self.y += self.velocity as i32;
+        
+
+        if self.y < DEFAULT_PARAMETERS.world_top {
+            self.y = DEFAULT_PARAMETERS.world_top;
+        }
+    }
+
+    fn flap(&mut self, physics: &PlayerPhysics) {
+        self.velocity = physics.flap_velocity;
+    }
+
+    // 风区的力，以重力为单位；下沉气流也不会让下落速度超过上限
+    fn push(&mut self, force: f32, physics: &PlayerPhysics) {
+        self.velocity = f32::min(self.velocity + force * physics.gravity, physics.max_fall_speed);
+    }
+
+    // 模拟扇翅膀：按下时只给一部分起飞速度，之后按住时由 boost 继续加速
+    fn tap(&mut self, physics: &PlayerPhysics) {
+        self.velocity = physics.flap_velocity * DEFAULT_PARAMETERS.flap_tap_fraction;
+    }
+
+    fn boost(&mut self, physics: &PlayerPhysics) {
+        let cap = physics.flap_velocity * DEFAULT_PARAMETERS.flap_hold_cap;
+        self.velocity = f32::max(cap, self.velocity + physics.flap_velocity * DEFAULT_PARAMETERS.flap_hold_boost);
+    }
+}
+
+impl Obstacle {
+    fn new(x: i32, score: i32, gap_adjust: i32, random: &mut RandomNumberGenerator) -> Self {
+        let mut obstacle = Self {
+            x: 0.0,
+            gap_y: 0,
+            second_gap_y: None,
+            size: 0,
+            scored: false,
+            near_miss: false,
+            near_miss_awarded: false,
+            telegraph: false,
+            width: 1,
+            closing: None,
+        };
+        obstacle.reset(x, score, gap_adjust, random);
+        obstacle
+    }
+
+    // 把每个字段都重新设置一遍，对象池里取出的旧管道和新建的没有区别
+    // gap_adjust 加到普通缺口的高度上，但不会小于 gap_min
+    fn reset(&mut self, x: i32, score: i32, gap_adjust: i32, random: &mut RandomNumberGenerator) {
+        self.x = x as f32;
+        self.second_gap_y = None;
+        self.scored = false; // 初始状态未得分
+        self.near_miss = false;
+        self.near_miss_awarded = false;
+        self.telegraph = false;
+        self.width = DEFAULT_PARAMETERS.obstacle_width;
+        self.closing = None;
+
+        // 分数够高后偶尔出现双缺口管道：两个较窄的缺口，中间隔着一段横杆，上下都留出空隙
+        if score >= DEFAULT_PARAMETERS.double_gap_min_score && random.range(0, 100) < DEFAULT_PARAMETERS.double_gap_chance {
+            let size = DEFAULT_PARAMETERS.double_gap_size;
+            let spacing = size + DEFAULT_PARAMETERS.double_gap_bar;
+            let margin = 4 + size / 2;
+            self.size = size;
+            self.gap_y = random.range(margin, ground_top() - margin - spacing + 1);
+            self.second_gap_y = Some(self.gap_y + spacing);
+        } else {
+            self.gap_y = random.range(30, 60);
+            self.size = i32::max(DEFAULT_PARAMETERS.gap_min, DEFAULT_PARAMETERS.gap_size(score) + gap_adjust);
+            // 分数够高后偶尔出现合拢管道：缺口先大一些，越靠近玩家越窄
+            if score >= DEFAULT_PARAMETERS.closing_min_score && random.range(0, 100) < DEFAULT_PARAMETERS.closing_chance {
+                self.size += DEFAULT_PARAMETERS.closing_extra;
+                self.closing = Some(self.size as f32);
+            }
+        }
+    }
+
+    fn update(&mut self, speed: f32) {
+        self.x -= speed;
+        // 缺口最小只缩到玩家高度加 gap_margin，总能穿过去；碰撞和渲染都直接用缩小后的 size
+        if let Some(size) = &mut self.closing {
+            let passable = (DEFAULT_PARAMETERS.player_height + DEFAULT_PARAMETERS.gap_margin) as f32;
+            *size = f32::max(passable, *size - speed * DEFAULT_PARAMETERS.closing_rate);
+            self.size = size.ceil() as i32;
+        }
+    }
+
+    // 管道占据的列是 left..right，得分、碰撞和擦边都按这两条边判断
+    fn left(&self) -> i32 {
+        self.x as i32
+    }
+
+    fn right(&self) -> i32 {
+        self.left() + self.width
+    }
+
+    // 双缺口和合拢管道比普通管道难，进屏幕前要提示
+    fn is_special(&self) -> bool {
+        self.second_gap_y.is_some() || self.closing.is_some()
+    }
+
+    // 最右一列也移到屏幕左边缘时才算完全离开
+    fn is_offscreen(&self) -> bool {
+        self.x + (self.width - 1) as f32 <= 0.0
+    }
+
+    // 每个缺口的上边缘和下边缘（不含），从上到下排列
+    fn gaps(&self) -> impl Iterator<Item = (i32, i32)> {
+        let half_size = self.size / 2;
+        std::iter::once(self.gap_y)
+            .chain(self.second_gap_y)
+            .map(move |gap_y| (gap_y - half_size, gap_y + half_size))
+    }
+
+    fn in_gap(&self, y: i32) -> bool {
+        self.gaps().any(|(top, bottom)| (top..bottom).contains(&y))
+    }
+
+    // 从屏幕顶部到地面之间管道实体占据的行
+    fn solid_rows(&self) -> impl Iterator<Item = i32> + '_ {
+        (0..ground_top()).filter(|y| !self.in_gap(*y))
+    }
+
+    fn render(&self, ctx: &mut BTerm, viewport: &Viewport, theme: &Theme, trail: f32) {
+        // 擦边时这一帧用高亮色绘制；开了设置时已经穿过的管道换成调暗的颜色，密集的地方分得清哪些已经过了
+        let base = match theme.passed_pipe {
+            Some(passed) if self.scored => passed.rgb().lerp(RGB::named(BLACK), 0.5),
+            _ => RGB::named(if self.near_miss { CYAN } else { YELLOW }),
+        };
+        let (_, cap) = theme.pipe_glyphs.glyphs();
+
+        // 管道在两格之间时，两端各画半格，移动时不会一格一格地跳。
+        // 起点和碰撞箱一样按 left() 取整，画出来的位置和实际判定的位置一致
+        let x = self.left() as f32;
+        let fraction = self.x - x;
+        let split = viewport.scale_x == 1.0 && (0.25..0.75).contains(&fraction);
+
+        // 绘制缺口以外到地面为止的各段管道；紧挨缺口的一格画管口，开启羽化时管口颜色暗一些
+        for y in self.solid_rows() {
+            let edge = self.in_gap(y - 1) || self.in_gap(y + 1);
+            let color = if edge && theme.gap_feather {
+                base.lerp(RGB::named(BLACK), 0.45)
+            } else {
+                base
+            };
+            let glyph_at = |column: i32| if edge { cap } else { theme.pipe_glyphs.texel(column, y) };
+            // 分两格画时左右两端各是半格，中间的列照常整格画。
+            // 半格也按当前样式取字符：实心块换成对应的半格字符，其他花纹照画，颜色暗一半表示只盖住半格
+            let columns = if split {
+                let halves = [(0, x, to_cp437('▐')), (self.width - 1, x + self.width as f32, to_cp437('▌'))];
+                for (column, cell_x, half_block) in halves {
+                    if let Some((screen_x, screen_y)) = viewport.to_screen(cell_x, y) {
+                        let glyph = glyph_at(column);
+                        if glyph == to_cp437('█') {
+                            ctx.set(screen_x, screen_y, color, RGBA::from_u8(0, 0, 0, 0), half_block);
+                        } else {
+                            ctx.set(screen_x, screen_y, RED, color.lerp(RGB::named(BLACK), 0.5), glyph);
+                        }
+                    }
+                }
+                1..self.width
+            } else {
+                0..self.width
+            };
+            for column in columns {
+                if let Some((screen_x, screen_y)) = viewport.to_screen(x + column as f32, y) {
+                    ctx.set(screen_x, screen_y, RED, color, glyph_at(column));
+                }
+            }
+        }
+
+        if trail > 0.0 {
+            let last_column = x + (self.width - 1) as f32;
+            self.render_trail(ctx, viewport, if split { last_column + 1.0 } else { last_column }, trail);
+        }
+    }
+
+    // 管道右侧（身后）的拖影：速度越快列数越多、颜色越亮，越远越暗
+    fn render_trail(&self, ctx: &mut BTerm, viewport: &Viewport, x: f32, intensity: f32) {
+        let columns = (intensity * DEFAULT_PARAMETERS.trail_max_columns as f32).ceil() as i32;
+        let glyphs = ['▒', '░', '·'];
+        let transparent = RGBA::from_u8(0, 0, 0, 0);
+        for column in 1..=columns {
+            let fade = column as f32 / (columns + 1) as f32;
+            let color = RGB::named(YELLOW).lerp(RGB::named(BLACK), 1.0 - intensity * (1.0 - fade));
+            let glyph = to_cp437(glyphs[(column as usize - 1).min(glyphs.len() - 1)]);
+            for y in self.solid_rows() {
+                if let Some((screen_x, screen_y)) = viewport.to_screen(x + column as f32, y) {
+                    ctx.set(screen_x, screen_y, color, transparent, glyph);
+                }
+            }
+        }
+    }
+
+    // 玩家和管道是否有重叠的列，不看高度
+    fn overlaps(&self, player: &Player) -> bool {
+        player.x < self.right() && player.x + DEFAULT_PARAMETERS.player_width > self.left()
+    }
+
+    // 玩家与所在缺口上下边缘的最近距离；玩家和管道没有重叠的列时返回 None，负数表示已经撞上。
+    // 双缺口管道取两个缺口中离得最宽松的那个，穿过任意一个都安全
+    fn edge_distance(&self, player: &Player) -> Option<i32> {
+        if !self.overlaps(player) {
+            return None;
+        }
+        self.gaps()
+            .map(|(top, bottom)| {
+                let above = player.y - top;
+                let below = bottom - (player.y + DEFAULT_PARAMETERS.player_height);
+                i32::min(above, below)
+            })
+            .max()
+    }
+
+    fn hit_obstacle(&self, player: &Player) -> bool {
+        matches!(self.edge_distance(player), Some(distance) if distance < 0)
+    }
+
+    fn is_near_miss(&self, player: &Player) -> bool {
+        matches!(self.edge_distance(player), Some(distance) if (0..=DEFAULT_PARAMETERS.near_miss_distance).contains(&distance))
+    }
+}
+
+impl Achievement {
+    const ALL: [Achievement; 7] = [
+        Achievement::FirstPipe,
+        Achievement::Pipes10,
+        Achievement::Pipes50,
+        Achievement::Score50,
+        Achievement::Perfect5,
+        Achievement::Coins10,
+        Achievement::HardPipes20,
+    ];
+
+    // 存档里用的标识，改名字不影响已解锁的成就
+    fn id(&self) -> &'static str {
+        match self {
+            Achievement::FirstPipe => "first_pipe",
+            Achievement::Pipes10 => "pipes_10",
+            Achievement::Pipes50 => "pipes_50",
+            Achievement::Score50 => "score_50",
+            Achievement::Perfect5 => "perfect_5",
+            Achievement::Coins10 => "coins_10",
+            Achievement::HardPipes20 => "hard_pipes_20",
+        }
+    }
+
+    fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|achievement| achievement.id() == id.trim())
+    }
+
+    fn name(&self) -> Text {
+        match self {
+            Achievement::FirstPipe => Text::AchievementFirstPipe,
+            Achievement::Pipes10 => Text::AchievementPipes10,
+            Achievement::Pipes50 => Text::AchievementPipes50,
+            Achievement::Score50 => Text::AchievementScore50,
+            Achievement::Perfect5 => Text::AchievementPerfect5,
+            Achievement::Coins10 => Text::AchievementCoins10,
+            Achievement::HardPipes20 => Text::AchievementHardPipes20,
+        }
+    }
+
+    fn description(&self) -> Text {
+        match self {
+            Achievement::FirstPipe => Text::AchievementFirstPipeGoal,
+            Achievement::Pipes10 => Text::AchievementPipes10Goal,
+            Achievement::Pipes50 => Text::AchievementPipes50Goal,
+            Achievement::Score50 => Text::AchievementScore50Goal,
+            Achievement::Perfect5 => Text::AchievementPerfect5Goal,
+            Achievement::Coins10 => Text::AchievementCoins10Goal,
+            Achievement::HardPipes20 => Text::AchievementHardPipes20Goal,
+        }
+    }
+
+    // 都按本局的数据判断；完美穿过指没有擦边，丢命后连续数会清零
+    fn is_met(&self, state: &State) -> bool {
+        match self {
+            Achievement::FirstPipe => state.run.pipes_passed >= 1,
+            Achievement::Pipes10 => state.run.pipes_passed >= 10,
+            Achievement::Pipes50 => state.run.pipes_passed >= 50,
+            Achievement::Score50 => state.run.score >= 50,
+            Achievement::Perfect5 => state.run.clean_passes >= 5,
+            Achievement::Coins10 => state.run.coins >= 10,
+            Achievement::HardPipes20 => state.run.flags.difficulty == Difficulty::Hard && state.run.pipes_passed >= 20,
+        }
+    }
+}
+
+impl Coin {
+    fn new(x: f32, y: i32) -> Self {
+        Self { x, y }
+    }
+
+    fn update(&mut self, speed: f32) {
+        self.x -= speed;
+    }
+
+    fn render(&self, ctx: &mut BTerm, viewport: &Viewport) {
+        if let Some((screen_x, screen_y)) = viewport.to_screen(self.x, self.y) {
+            ctx.set(screen_x, screen_y, GOLD, RGBA::from_u8(0, 0, 0, 0), to_cp437('$'));
+        }
+    }
+
+    // 金币只占一格，落在玩家的碰撞框里就算收到
+    fn touches(&self, player: &Player) -> bool {
+        (player.x..player.x + DEFAULT_PARAMETERS.player_width).contains(&(self.x as i32))
+            && (player.y..player.y + DEFAULT_PARAMETERS.player_height).contains(&self.y)
+    }
+}
+
+impl WindZone {
+    // 竖直位置和方向都随机，保证整块区域在能飞到的范围内
+    fn new(x: f32, random: &mut RandomNumberGenerator) -> Self {
+        let top = random.range(DEFAULT_PARAMETERS.world_top, DEFAULT_PARAMETERS.world_bottom - DEFAULT_PARAMETERS.wind_height);
+        let direction = if random.range(0, 2) == 0 { -1.0 } else { 1.0 };
+        Self {
+            x,
+            top,
+            bottom: top + DEFAULT_PARAMETERS.wind_height,
+            force: direction * DEFAULT_PARAMETERS.wind_strength,
+        }
+    }
+
+    fn update(&mut self, speed: f32) {
+        self.x -= speed;
+    }
+
+    fn right(&self) -> i32 {
+        self.x as i32 + DEFAULT_PARAMETERS.wind_width
+    }
+
+    // 玩家中心在风区里才受力，只擦到边不算
+    fn contains(&self, player: &Player) -> bool {
+        let center_x = player.x + DEFAULT_PARAMETERS.player_width / 2;
+        let center_y = player.y + DEFAULT_PARAMETERS.player_height / 2;
+        (self.x as i32..self.right()).contains(&center_x) && (self.top..self.bottom).contains(&center_y)
+    }
+
+    // 斜着错开的粒子，上升气流往上飘，下沉气流往下飘
+    fn render(&self, ctx: &mut BTerm, viewport: &Viewport, drift: i32) {
+        let (color, phase) = if self.force < 0.0 { (CYAN, drift) } else { (STEEL_BLUE, -drift) };
+        for column in 0..DEFAULT_PARAMETERS.wind_width {
+            for y in self.top..self.bottom {
+                if (y - self.top + phase + column * 3).rem_euclid(5) != 0 {
+                    continue;
+                }
+                if let Some((screen_x, screen_y)) = viewport.to_screen(self.x + column as f32, y) {
+                    ctx.set(screen_x, screen_y, color, RGBA::from_u8(0, 0, 0, 0), to_cp437('·'));
+                }
+            }
+        }
+    }
+}
+
+impl PipeGlyphs {
+    // 菜单上显示的名字，按当前语言翻译
+    fn label(&self) -> Text {
+        match self {
+            PipeGlyphs::Classic => Text::Classic,
+            PipeGlyphs::Solid => Text::Solid,
+            PipeGlyphs::Shade => Text::Shade,
+            PipeGlyphs::Hash => Text::Hash,
+            PipeGlyphs::Brick => Text::Brick,
+        }
+    }
+
+    // (管身, 管口)
+    fn glyphs(&self) -> (FontCharType, FontCharType) {
+        match self {
+            PipeGlyphs::Classic => (to_cp437('|'), to_cp437('|')),
+            PipeGlyphs::Solid => (to_cp437('█'), to_cp437('▓')),
+            PipeGlyphs::Shade => (to_cp437('▒'), to_cp437('█')),
+            PipeGlyphs::Hash => (to_cp437('#'), to_cp437('=')),
+            PipeGlyphs::Brick => (to_cp437('─'), to_cp437('═')),
+        }
+    }
+
+    // 管身 (u, v) 处的字符。u 是相对管道左边缘的列，不是屏幕列，所以花纹贴在管道上跟着移动，
+    // 不会像管道从固定的图案下面滑过去
+    fn texel(&self, u: i32, v: i32) -> FontCharType {
+        let (body, _) = self.glyphs();
+        if *self != PipeGlyphs::Brick {
+            return body;
+        }
+        // 每三行一层砖：一行灰缝加两行砖，相邻两层的竖缝错开两列
+        let course = v / 3;
+        if v % 3 == 0 {
+            body
+        } else if (u + course * 2) % 4 == 0 {
+            to_cp437('│')
+        } else {
+            to_cp437(' ')
+        }
+    }
+
+    fn cycle(&self, delta: i32) -> Self {
+        let all = [PipeGlyphs::Classic, PipeGlyphs::Solid, PipeGlyphs::Shade, PipeGlyphs::Hash, PipeGlyphs::Brick];
+        let index = all.iter().position(|g| g == self).unwrap_or(0) as i32;
+        all[(index + delta).rem_euclid(all.len() as i32) as usize]
+    }
+}
+
+impl KeyBindings {
+    // 可以绑定的键：避开菜单、结束界面和游戏中已经有用途的键（P、Tab、Esc、S、C 等）
+    const FLAP_KEYS: [VirtualKeyCode; 7] = [
+        VirtualKeyCode::Space,
+        VirtualKeyCode::Up,
+        VirtualKeyCode::W,
+        VirtualKeyCode::Z,
+        VirtualKeyCode::X,
+        VirtualKeyCode::J,
+        VirtualKeyCode::K,
+    ];
+    const FAST_FALL_KEYS: [VirtualKeyCode; 5] = [
+        VirtualKeyCode::Down,
+        VirtualKeyCode::LShift,
+        VirtualKeyCode::LControl,
+        VirtualKeyCode::Z,
+        VirtualKeyCode::X,
+    ];
+
+    // 在候选键里前后切换，跳过已经绑给另一个动作的键
+    fn cycle_key(current: VirtualKeyCode, candidates: &[VirtualKeyCode], taken: VirtualKeyCode, delta: i32) -> VirtualKeyCode {
+        let len = candidates.len() as i32;
+        let index = candidates.iter().position(|key| *key == current).unwrap_or(0) as i32;
+        (1..=len)
+            .map(|step| candidates[(index + step * delta.signum()).rem_euclid(len) as usize])
+            .find(|key| *key != taken)
+            .unwrap_or(current)
+    }
+}
+
+impl Theme {
+    fn menu_item_color(&self, selected: bool) -> RGB {
+        if selected {
+            self.menu_highlight.rgb()
+        } else {
+            self.menu_text.rgb()
+        }
+    }
+}
+
+impl ThemeColor {
+    const ALL: [ThemeColor; 7] = [
+        ThemeColor::Yellow,
+        ThemeColor::White,
+        ThemeColor::Cyan,
+        ThemeColor::Magenta,
+        ThemeColor::Orange,
+        ThemeColor::Green,
+        ThemeColor::Gray,
+    ];
+
+    // 菜单上显示的名字，按当前语言翻译
+    fn label(&self) -> Text {
+        match self {
+            ThemeColor::Yellow => Text::Yellow,
+            ThemeColor::White => Text::White,
+            ThemeColor::Cyan => Text::Cyan,
+            ThemeColor::Magenta => Text::Magenta,
+            ThemeColor::Orange => Text::Orange,
+            ThemeColor::Green => Text::Green,
+            ThemeColor::Gray => Text::Gray,
+        }
+    }
+
+    fn rgb(&self) -> RGB {
+        RGB::named(match self {
+            ThemeColor::Yellow => YELLOW,
+            ThemeColor::White => WHITE,
+            ThemeColor::Cyan => CYAN,
+            ThemeColor::Magenta => MAGENTA,
+            ThemeColor::Orange => ORANGE,
+            ThemeColor::Green => GREEN,
+            ThemeColor::Gray => GRAY,
+        })
+    }
+
+    fn cycle(&self, delta: i32) -> Self {
+        let index = Self::ALL.iter().position(|c| c == self).unwrap_or(0) as i32;
+        Self::ALL[(index + delta).rem_euclid(Self::ALL.len() as i32) as usize]
+    }
+}
+
+impl RunMode {
+    const ALL: [RunMode; 5] = [RunMode::Classic, RunMode::Combo, RunMode::Sandbox, RunMode::Zen, RunMode::Marathon];
+
+    // 菜单上显示的名字，按当前语言翻译
+    fn label(&self) -> Text {
+        match self {
+            RunMode::Classic => Text::Classic,
+            RunMode::Combo => Text::Combo,
+            RunMode::Sandbox => Text::Sandbox,
+            RunMode::Zen => Text::Zen,
+            RunMode::Marathon => Text::Marathon,
+        }
+    }
+
+    fn cycle(&self, delta: i32) -> Self {
+        let index = Self::ALL.iter().position(|m| m == self).unwrap_or(0) as i32;
+        Self::ALL[(index + delta).rem_euclid(Self::ALL.len() as i32) as usize]
+    }
+}
+
+impl ShareCode {
+    // 去掉了容易看错的 I、L、O、U
+    const ALPHABET: &'static [u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+    // 80 位：种子 64 位，难度 2 位，玩法 3 位，障碍物间距 6 位，自适应等级 4 位，固定间距 1 位
+    const DATA_LEN: usize = 16;
+    const LEN: usize = Self::DATA_LEN + 1; // 最后一位是校验位
+    const LEGACY_LEN: usize = 16; // 旧版分享码只有前 75 位，后面的字段当作 0（没有等级、间距浮动）
+
+    // 每 4 个字符用 - 隔开，例如 1A2B-3C4D-5E6F-7G8H-J
+    fn encode(&self) -> String {
+        let difficulty = Difficulty::ALL.iter().position(|d| *d == self.difficulty).unwrap_or(0);
+        let run_mode = RunMode::ALL.iter().position(|m| *m == self.run_mode).unwrap_or(0);
+        // 0 表示没有等级，1~9 对应 -1.0~1.0，每 0.25 一档
+        let level = self.adaptive_level.map_or(0, |level| ((level.clamp(-1.0, 1.0) + 1.0) * 4.0).round() as u128 + 1);
+        let bits = self.seed as u128
+            | (difficulty as u128) << 64
+            | (run_mode as u128) << 66
+            | (self.obstacle_distance as u128 & 0b111111) << 69
+            | level << 75
+            | (!self.spacing_jitter as u128) << 79;
+
+        let mut digits: Vec<u8> = (0..Self::DATA_LEN).map(|i| ((bits >> (5 * i)) & 31) as u8).collect();
+        digits.push(Self::checksum(&digits));
+        let chunks: Vec<String> = digits
+            .chunks(4)
+            .map(|chunk| chunk.iter().map(|d| Self::ALPHABET[*d as usize] as char).collect())
+            .collect();
+        chunks.join("-")
+    }
+
+    // 忽略大小写、空格和 -，任何不合法的输入都返回错误说明
+    fn decode(code: &str) -> Result<Self, String> {
+        let digits = code
+            .chars()
+            .filter(|c| *c != '-' && !c.is_whitespace())
+            .map(|c| {
+                Self::ALPHABET
+                    .iter()
+                    .position(|a| *a as char == c.to_ascii_uppercase())
+                    .map(|d| d as u8)
+                    .ok_or(format!("Invalid character: {}", c))
+            })
+            .collect::<Result<Vec<u8>, String>>()?;
+        if digits.len() != Self::LEN && digits.len() != Self::LEGACY_LEN {
+            return Err(format!("Share code must be {} characters", Self::LEN));
+        }
+        let (data, check) = digits.split_at(digits.len() - 1);
+        if Self::checksum(data) != check[0] {
+            return Err("Share code is mistyped".to_string());
+        }
+
+        let bits = data.iter().enumerate().fold(0u128, |bits, (i, d)| bits | (*d as u128) << (5 * i));
+        let difficulty = *Difficulty::ALL.get(((bits >> 64) & 0b11) as usize).ok_or("Unknown difficulty in share code")?;
+        let run_mode = *RunMode::ALL.get(((bits >> 66) & 0b111) as usize).ok_or("Unknown mode in share code")?;
+        let obstacle_distance = ((bits >> 69) & 0b111111) as i32;
+        if !(40..=60).contains(&obstacle_distance) {
+            return Err("Invalid obstacle distance in share code".to_string());
+        }
+        let adaptive_level = match ((bits >> 75) & 0b1111) as i32 {
+            0 => None,
+            level @ 1..=9 => Some((level - 1) as f32 / 4.0 - 1.0),
+            _ => return Err("Invalid adaptive level in share code".to_string()),
+        };
+        // 存的是“关掉浮动”，旧分享码的 0 正好对应默认的开启
+        let spacing_jitter = (bits >> 79) & 1 == 0;
+        Ok(Self {
+            seed: bits as u64,
+            difficulty,
+            run_mode,
+            obstacle_distance,
+            adaptive_level,
+            spacing_jitter,
+        })
+    }
+
+    // 按位置加权求和，输错一个字符或相邻两位颠倒通常都能发现
+    fn checksum(digits: &[u8]) -> u8 {
+        let sum: u32 = digits.iter().enumerate().map(|(i, d)| (i as u32 + 1) * *d as u32).sum();
+        (sum % 32) as u8
+    }
+}
+
+impl PlayerPhysics {
+    // 速度单位是“格/步”，步长变了就按比例缩放速度、按平方缩放重力，同样时间内飞出的轨迹不变
+    fn rescaled(self, frame_duration: f32) -> Self {
+        let ratio = frame_duration / DEFAULT_PARAMETERS.frame_duration;
+        Self {
+            gravity: self.gravity * ratio * ratio,
+            max_fall_speed: self.max_fall_speed * ratio,
+            flap_velocity: self.flap_velocity * ratio,
+            curve: self.curve,
+        }
+    }
+}
+
+impl GravityCurve {
+    const ALL: [GravityCurve; 3] = [GravityCurve::Linear, GravityCurve::Quadratic, GravityCurve::Snappy];
+
+    // 菜单上显示的名字，按当前语言翻译
+    fn label(&self) -> Text {
+        match self {
+            GravityCurve::Linear => Text::Linear,
+            GravityCurve::Quadratic => Text::Quadratic,
+            GravityCurve::Snappy => Text::Snappy,
+        }
+    }
+
+    fn cycle(&self, delta: i32) -> Self {
+        let index = Self::ALL.iter().position(|c| c == self).unwrap_or(0) as i32;
+        Self::ALL[(index + delta).rem_euclid(Self::ALL.len() as i32) as usize]
+    }
+
+    // 一个物理帧之后的速度
+    fn accelerate(&self, velocity: f32, physics: &PlayerPhysics) -> f32 {
+        match self {
+            // 保持原来的算法：没到最大速度就加一次重力
+            GravityCurve::Linear => {
+                if velocity < physics.max_fall_speed {
+                    velocity + physics.gravity
+                } else {
+                    velocity
+                }
+            }
+            // 从顶点的一半重力逐渐增加到接近最大速度时的两倍重力
+            GravityCurve::Quadratic => {
+                let falling = (velocity / physics.max_fall_speed).clamp(0.0, 1.0);
+                let gravity = physics.gravity * (0.5 + 1.5 * falling);
+                f32::min(velocity + gravity, physics.max_fall_speed)
+            }
+            GravityCurve::Snappy => {
+                let gravity = if velocity < 0.0 { physics.gravity } else { physics.gravity * 2.0 };
+                f32::min(velocity + gravity, physics.max_fall_speed)
+            }
+        }
+    }
+}
+
+impl ScrollDirection {
+    // 菜单上显示的名字，按当前语言翻译
+    fn label(&self) -> Text {
+        match self {
+            ScrollDirection::RightToLeft => Text::RightToLeft,
+            ScrollDirection::LeftToRight => Text::LeftToRight,
+            ScrollDirection::Vertical => Text::Vertical,
+        }
+    }
+
+    fn cycle(&self, delta: i32) -> Self {
+        let all = [ScrollDirection::RightToLeft, ScrollDirection::LeftToRight, ScrollDirection::Vertical];
+        let index = all.iter().position(|d| d == self).unwrap_or(0) as i32;
+        all[(index + delta).rem_euclid(all.len() as i32) as usize]
+    }
+}
+
+impl BackgroundStyle {
+    const ALL: [BackgroundStyle; 3] = [BackgroundStyle::Stars, BackgroundStyle::Clouds, BackgroundStyle::Mountains];
+
+    fn cycle(&self, delta: i32) -> Self {
+        let index = Self::ALL.iter().position(|s| s == self).unwrap_or(0) as i32;
+        Self::ALL[(index + delta).rem_euclid(Self::ALL.len() as i32) as usize]
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "stars" => Some(BackgroundStyle::Stars),
+            "clouds" => Some(BackgroundStyle::Clouds),
+            "mountains" => Some(BackgroundStyle::Mountains),
+            _ => None,
+        }
+    }
+}
+
+impl PlayerStyle {
+    const ALL: [PlayerStyle; 3] = [PlayerStyle::Dragon, PlayerStyle::Bird, PlayerStyle::Duck];
+
+    fn name(&self) -> &'static str {
+        match self {
+            PlayerStyle::Dragon => "Dragon",
+            PlayerStyle::Bird => "Bird",
+            PlayerStyle::Duck => "Duck",
+        }
+    }
+
+    // 菜单和 HUD 上显示的名字，按当前语言翻译；name() 是存档和命令行里用的固定写法
+    fn label(&self) -> Text {
+        match self {
+            PlayerStyle::Dragon => Text::Dragon,
+            PlayerStyle::Bird => Text::Bird,
+            PlayerStyle::Duck => Text::Duck,
+        }
+    }
+
+    // 解锁需要的金币，鸭子是默认皮肤，免费
+    fn unlock_cost(&self) -> i32 {
+        match self {
+            PlayerStyle::Dragon => 60,
+            PlayerStyle::Bird => 30,
+            PlayerStyle::Duck => 0,
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "dragon" => Some(PlayerStyle::Dragon),
+            "bird" => Some(PlayerStyle::Bird),
+            "duck" => Some(PlayerStyle::Duck),
+            _ => None,
+        }
+    }
+
+    // 龙更重、扇得更有力，鸭子更轻；鸟保持原来的手感
+    fn physics(&self) -> PlayerPhysics {
+        match self {
+            PlayerStyle::Dragon => PlayerPhysics { gravity: 0.24, max_fall_speed: 2.2, flap_velocity: -2.8, curve: GravityCurve::Linear },
+            PlayerStyle::Bird => PlayerPhysics { gravity: 0.2, max_fall_speed: 2.0, flap_velocity: -2.5, curve: GravityCurve::Linear },
+            PlayerStyle::Duck => PlayerPhysics { gravity: 0.17, max_fall_speed: 1.8, flap_velocity: -2.3, curve: GravityCurve::Linear },
+        }
+    }
+}
+
+impl Difficulty {
+    const ALL: [Difficulty; 4] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard, Difficulty::Adaptive];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Normal => "Normal",
+            Difficulty::Hard => "Hard",
+            Difficulty::Adaptive => "Adaptive",
+        }
+    }
+
+    // 菜单和 HUD 上显示的名字，按当前语言翻译；name() 是存档和命令行里用的固定写法
+    fn label(&self) -> Text {
+        match self {
+            Difficulty::Easy => Text::Easy,
+            Difficulty::Normal => Text::Normal,
+            Difficulty::Hard => Text::Hard,
+            Difficulty::Adaptive => Text::Adaptive,
+        }
+    }
+
+    fn next(&self) -> Self {
+        match self {
+            Difficulty::Easy => Difficulty::Normal,
+            Difficulty::Normal => Difficulty::Hard,
+            Difficulty::Hard => Difficulty::Adaptive,
+            Difficulty::Adaptive => Difficulty::Easy,
+        }
+    }
+
+    fn prev(&self) -> Self {
+        match self {
+            Difficulty::Easy => Difficulty::Adaptive,
+            Difficulty::Normal => Difficulty::Easy,
+            Difficulty::Hard => Difficulty::Normal,
+            Difficulty::Adaptive => Difficulty::Hard,
+        }
+    }
+
+    // 每穿过一根管道（以及擦边奖励）得分的倍率
+    fn score_multiplier(&self) -> i32 {
+        match self {
+            Difficulty::Easy => 1,
+            Difficulty::Normal | Difficulty::Adaptive => 1,
+            Difficulty::Hard => 2,
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|difficulty| difficulty.name().eq_ignore_ascii_case(name))
+    }
+
+    // 第一根管道相对屏幕右边缘的额外距离，越大反应时间越长
+    fn first_obstacle_runway(&self) -> i32 {
+        match self {
+            Difficulty::Easy => 40,
+            Difficulty::Normal | Difficulty::Adaptive => 0,
+            Difficulty::Hard => -20,
+        }
+    }
+
+    // 默认的开局保护时间（毫秒）
+    fn grace_ms(&self) -> i32 {
+        match self {
+            Difficulty::Easy => 1500,
+            Difficulty::Normal | Difficulty::Adaptive => 750,
+            Difficulty::Hard => 0,
+        }
+    }
+
+    // 物理步长（毫秒）：越短玩家反应越快、越灵敏
+    fn frame_duration(&self) -> f32 {
+        match self {
+            Difficulty::Easy => 85.0,
+            Difficulty::Normal | Difficulty::Adaptive => DEFAULT_PARAMETERS.frame_duration,
+            Difficulty::Hard => 65.0,
+        }
+    }
+
+    // 障碍物速度倍率
+    fn speed_factor(&self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.8,
+            Difficulty::Normal | Difficulty::Adaptive => 1.0,
+            Difficulty::Hard => 1.25,
+        }
+    }
+}
+
+impl PhotoMode {
+    // 按当前的平移和倍率取景后的整屏格子；放大以屏幕中心为准，移出冻结画面的部分用留白的颜色
+    fn view(&self) -> Vec<(RGB, bool)> {
+        let width = DEFAULT_PARAMETERS.screen_width;
+        let height = DEFAULT_PARAMETERS.screen_height;
+        let center_x = width as f32 / 2.0;
+        let center_y = height as f32 / 2.0;
+        let mut view = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let source_x = ((x as f32 + 0.5 - center_x) / self.zoom + center_x + self.pan_x).floor() as i32;
+                let source_y = ((y as f32 + 0.5 - center_y) / self.zoom + center_y + self.pan_y).floor() as i32;
+                if (0..width).contains(&source_x) && (0..height).contains(&source_y) {
+                    view.push(self.cells[(source_y * width + source_x) as usize]);
+                } else {
+                    view.push((DEFAULT_PARAMETERS.letterbox_color, false));
+                }
+            }
+        }
+        view
+    }
+}
+
+impl AdaptiveDifficulty {
+    fn record(&mut self, run_ms: f32) {
+        self.recent_runs.push_back(run_ms);
+        while self.recent_runs.len() > DEFAULT_PARAMETERS.adaptive_window {
+            self.recent_runs.pop_front();
+        }
+        self.pending = true;
+    }
+
+    // 倒带复活时撤销刚记下的一局，等这局真正结束再记
+    fn undo_last(&mut self) {
+        if self.pending {
+            self.recent_runs.pop_back();
+            self.pending = false;
+        }
+    }
+
+    // 开局时调用：最近几局平均活得太短就放松一档，活得很长就收紧一档，返回这一局用的等级
+    fn start_run(&mut self) -> f32 {
+        if self.pending && !self.recent_runs.is_empty() {
+            let average = self.recent_runs.iter().sum::<f32>() / self.recent_runs.len() as f32;
+            if average < DEFAULT_PARAMETERS.adaptive_short_ms {
+                self.level -= DEFAULT_PARAMETERS.adaptive_step;
+            } else if average > DEFAULT_PARAMETERS.adaptive_long_ms {
+                self.level += DEFAULT_PARAMETERS.adaptive_step;
+            }
+            self.level = self.level.clamp(-1.0, 1.0);
+        }
+        self.pending = false;
+        self.level
+    }
+}
+
+impl Settings {
+    // 玩家样式决定基础手感，下落曲线来自设置
+    fn physics(&self) -> PlayerPhysics {
+        PlayerPhysics {
+            curve: self.gravity_curve,
+            ..self.player_style.physics()
+        }
+    }
+
+    fn start_grace_ms(&self) -> i32 {
+        self.start_grace_ms.unwrap_or(self.difficulty.grace_ms())
+    }
+}
+
+impl RunState {
+    // 新一局的初始状态，重开时整个替换掉上一局的，不需要逐个字段清零
+    fn fresh_game(settings: &Settings, seed: u64, high_score: i32, adaptive_level: f32) -> Self {
+        let flags = RunFlags {
+            adaptive_level,
+            ..RunFlags::capture(settings)
+        };
+        // 开局的保护时间，期间撞管道不算
+        let grace = settings.start_grace_ms() as f32;
+        Self {
+            player: Player::start(),
+            frame_time: 0.0,
+            score: 0,
+            obstacle_list: Vec::new(),
+            // 第一根管道的位置随难度前后移动，后续管道的间距保持不变
+            distance: -flags.difficulty.first_obstacle_runway() as f32,
+            spacing_offset: 0,
+            flags,
+            lives: settings.starting_lives,
+            invuln_remaining: grace,
+            invuln_duration: grace,
+            end_message: None,
+            flap_cooldown_remaining: 0.0,
+            seed,
+            rng: RandomNumberGenerator::seeded(seed),
+            rewind_buffer: VecDeque::new(),
+            input_log: VecDeque::new(),
+            high_score_before_run: high_score,
+            recorded_on: None,
+            unsubmitted: false,
+            clean_passes: 0,
+            pipes_passed: 0,
+            coin_list: Vec::new(),
+            wind_zones: Vec::new(),
+            coins: 0,
+            toasts: VecDeque::new(),
+            toast_remaining_ms: 0.0,
+            time_ms: 0.0,
+            buffered_flap_until: None,
+            fast_falling: false,
+            flap_held: false,
+            flap_hold_remaining_ms: 0.0,
+            current_speed: 0.0,
+            time_scale: 1.0,
+            combo_glow: 0.0,
+            checkpoint: None,
+            checkpoint_retries: DEFAULT_PARAMETERS.checkpoint_retries,
+            end_confirm_quit: false,
+            background_offset: 0.0,
+        }
+    }
+}
+
+impl RunFlags {
+    fn capture(settings: &Settings) -> Self {
+        Self {
+            difficulty: settings.difficulty,
+            mode: settings.run_mode,
+            // 额外生命和马拉松的检查点都相当于复活；开局保护比难度默认的长也算辅助
+            assists_used: settings.starting_lives > 1
+                || settings.run_mode == RunMode::Marathon
+                || settings.start_grace_ms() > settings.difficulty.grace_ms()
+                || settings.ghost_obstacles
+                || settings.flap_assist != FlapAssist::Off
+                || settings.near_pipe_slowdown
+                || settings.ideal_path
+                || settings.safe_zone,
+            practice: false,
+            scoring: settings.scoring,
+            adaptive_level: 0.0,
+            fixed_level: false,
+        }
+    }
+
+    // 这局结束时是否计入自适应难度的统计
+    fn records_adaptive(&self) -> bool {
+        self.difficulty == Difficulty::Adaptive && !self.practice && !self.fixed_level
+    }
+
+    // 障碍物速度倍率：难度本身的倍率再按自适应等级上下浮动
+    fn speed_factor(&self) -> f32 {
+        self.difficulty.speed_factor() * (1.0 + self.adaptive_level * DEFAULT_PARAMETERS.adaptive_speed_range)
+    }
+
+    // 普通管道缺口高度的调整量，等级越高缺口越窄
+    fn gap_adjust(&self) -> i32 {
+        -(self.adaptive_level * DEFAULT_PARAMETERS.adaptive_gap_range as f32).round() as i32
+    }
+
+    // 困难难度、没有用过任何辅助、也不是练习局，才算硬核局
+    fn is_hardcore(&self) -> bool {
+        self.difficulty == Difficulty::Hard && !self.assists_used && !self.practice
+    }
+
+    fn leaderboard_kind(&self) -> LeaderboardKind {
+        if self.mode == RunMode::Zen {
+            return LeaderboardKind::Zen;
+        }
+        match (self.is_hardcore(), self.scoring) {
+            (true, Scoring::Points) => LeaderboardKind::Hardcore,
+            (false, Scoring::Points) => LeaderboardKind::Casual,
+            (true, Scoring::Time) => LeaderboardKind::HardcoreTime,
+            (false, Scoring::Time) => LeaderboardKind::CasualTime,
+        }
+    }
+}
+
+impl LeaderboardKind {
+    const ALL: [LeaderboardKind; 5] = [
+        LeaderboardKind::Casual,
+        LeaderboardKind::Hardcore,
+        LeaderboardKind::CasualTime,
+        LeaderboardKind::HardcoreTime,
+        LeaderboardKind::Zen,
+    ];
+
+    fn title(&self) -> &'static str {
+        match self {
+            LeaderboardKind::Casual => "Casual Leaderboard",
+            LeaderboardKind::Hardcore => "Hardcore Leaderboard",
+            LeaderboardKind::CasualTime => "Casual Leaderboard (Time)",
+            LeaderboardKind::HardcoreTime => "Hardcore Leaderboard (Time)",
+            LeaderboardKind::Zen => "Zen Leaderboard",
+        }
+    }
+
+    // 菜单和 HUD 上显示的名字，按当前语言翻译；title() 是提交成绩时用的固定写法
+    fn label(&self) -> Text {
+        match self {
+            LeaderboardKind::Casual => Text::CasualBoard,
+            LeaderboardKind::Hardcore => Text::HardcoreBoard,
+            LeaderboardKind::CasualTime => Text::CasualTimeBoard,
+            LeaderboardKind::HardcoreTime => Text::HardcoreTimeBoard,
+            LeaderboardKind::Zen => Text::ZenBoard,
+        }
+    }
+
+    fn file_name(&self) -> &'static str {
+        match self {
+            LeaderboardKind::Casual => "leaderboard_casual.txt",
+            LeaderboardKind::Hardcore => "leaderboard_hardcore.txt",
+            LeaderboardKind::CasualTime => "leaderboard_casual_time.txt",
+            LeaderboardKind::HardcoreTime => "leaderboard_hardcore_time.txt",
+            LeaderboardKind::Zen => "leaderboard_zen.txt",
+        }
+    }
+
+    fn cycle(&self, delta: i32) -> Self {
+        let index = Self::ALL.iter().position(|k| k == self).unwrap_or(0) as i32;
+        Self::ALL[(index + delta).rem_euclid(Self::ALL.len() as i32) as usize]
+    }
+
+    fn ranks_by_time(&self) -> bool {
+        matches!(self, LeaderboardKind::CasualTime | LeaderboardKind::HardcoreTime)
+    }
+
+    // 排名依据：时间榜比存活时间，其余比分数
+    fn rank_value(&self, entry: &LeaderboardEntry) -> i32 {
+        if self.ranks_by_time() { entry.time_ms } else { entry.score }
+    }
+}
+
+impl FlapAssist {
+    const ALL: [FlapAssist; 3] = [FlapAssist::Off, FlapAssist::Light, FlapAssist::Full];
+
+    // 菜单上显示的名字，按当前语言翻译
+    fn label(&self) -> Text {
+        match self {
+            FlapAssist::Off => Text::Off,
+            FlapAssist::Light => Text::Light,
+            FlapAssist::Full => Text::Full,
+        }
+    }
+
+    fn cycle(&self, delta: i32) -> Self {
+        let index = Self::ALL.iter().position(|a| a == self).unwrap_or(0) as i32;
+        Self::ALL[(index + delta).rem_euclid(Self::ALL.len() as i32) as usize]
+    }
+}
+
+impl Scoring {
+    const ALL: [Scoring; 2] = [Scoring::Points, Scoring::Time];
+
+    // 菜单上显示的名字，按当前语言翻译
+    fn label(&self) -> Text {
+        match self {
+            Scoring::Points => Text::Points,
+            Scoring::Time => Text::Time,
         }
     }
 
-    fn render_stars_background(&self, ctx: &mut BTerm) {
-        self.render_looping_background(ctx, &self.texture.background_stars);
+    fn cycle(&self, delta: i32) -> Self {
+        let index = Self::ALL.iter().position(|s| s == self).unwrap_or(0) as i32;
+        Self::ALL[(index + delta).rem_euclid(Self::ALL.len() as i32) as usize]
     }
+}
 
-    fn render_clouds_background(&self, ctx: &mut BTerm) {
-        self.render_looping_background(ctx, &self.texture.background_clouds);
+impl Language {
+    const ALL: [Language; 2] = [Language::English, Language::Spanish];
+
+    // 语言名用它自己的写法显示，切错了也认得出来
+    fn name(&self) -> &'static str {
+        match self {
+            Language::English => "English",
+            Language::Spanish => "Español",
+        }
     }
 
-    fn render_mountains_background(&self, ctx: &mut BTerm) {
-        self.render_looping_background(ctx, &self.texture.background_mountains);
+    fn cycle(&self, delta: i32) -> Self {
+        let index = Self::ALL.iter().position(|l| l == self).unwrap_or(0) as i32;
+        Self::ALL[(index + delta).rem_euclid(Self::ALL.len() as i32) as usize]
     }
 
-    fn render_looping_background(&self, ctx: &mut BTerm, background: &image::DynamicImage) {
-        let width = background.width() as i32;
-        let height = background.height() as i32;
-        let offset = self.background_offset as i32 % width; // 确保偏移量在合理范围内
+    // 其他语言的表里缺的 key 回退到英文
+    fn text(&self, key: Text) -> &'static str {
+        let translated = match self {
+            Language::English => None,
+            Language::Spanish => spanish_text(key),
+        };
+        translated.unwrap_or_else(|| english_text(key))
+    }
+}
 
-        for y in 0..DEFAULT_PARAMETERS.screen_height {
-            for x in 0..DEFAULT_PARAMETERS.screen_width {
-                let bg_x = (x + offset) % width;
-                let bg_y = y % height;
-                
-                let pixel = background.get_pixel(bg_x as u32, bg_y as u32);
-                let color = RGB::from_u8(pixel[0], pixel[1], pixel[2]);
-                ctx.set(x, y, BLACK,color, to_cp437(' '));
-            }
-        }
+// 英文是完整的基准表，新加的 Text 必须先在这里补上
+fn english_text(key: Text) -> &'static str {
+    match key {
+        Text::PressToFlap => "Press {} to flap",
+        Text::PhotoModeHint => "(P) Photo Mode",
+        Text::SandboxHint => "SANDBOX - Press Esc to return to the menu",
+        Text::HudLine => "Score: {} (x{} {})  Best: {}  Pipes: {}  Coins: {}  Time: {}",
+        Text::HudPlayer => "Player {}",
+        Text::HudLives => "Lives:",
+        Text::HudShield => "Shield",
+        Text::HudSpeed => "Speed",
+        Text::HudCombo => "x{} ({} clean)",
+        Text::AchievementUnlocked => "Achievement unlocked: {}",
+        Text::TutorialFlap => "Press {} to flap your wings",
+        Text::TutorialGaps => "Fly through the gaps between the pipes",
+        Text::TutorialAvoid => "Don't touch the pipes or the ground",
+        Text::TutorialDone => "Great! You're on your own now",
+        Text::TutorialScore => "Score: {}  Pipes: {}",
+        Text::SkipTutorial => "(Esc) Skip tutorial",
+        Text::DemoHint => "DEMO - Press any key",
+        Text::HotSeatScored => "Player 1 scored {}",
+        Text::HotSeatReady => "Player 2, get ready!",
+        Text::PressSpaceToStart => "Press SPACE to start",
+        Text::Player1Wins => "Player 1 wins!",
+        Text::Player2Wins => "Player 2 wins!",
+        Text::Tie => "It's a tie!",
+        Text::HotSeatResult => "Player {}: {}",
+        Text::PlayAgain => "(P) Play Again",
+        Text::MainMenuKey => "(M) Main Menu",
+        Text::YouAreDead => "You are dead!",
+        Text::FinalScore => "Final Score: {}  Pipes: {}  Time: {}  Coins: +{} ({})",
+        Text::HighScore => "High Score: {}",
+        Text::LastAndBest => "Last: {}  Best: {}",
+        Text::WindowTooSmall => "Window too small",
+        Text::WindowMinSize => "Resize it to at least {} x {} pixels",
+        Text::RecordedOn => "Recorded on: {}",
+        Text::PlayAgainRetry => "(P) Play Again  (T) Retry Same Course",
+        Text::ReallyQuit => "Really quit? (Y/N)",
+        Text::QuitGameKey => "(Q) Quit Game",
+        Text::SaveScreenshotKey => "(S) Save Screenshot",
+        Text::RewindKeys => "(B) Rewind 2 Seconds  (G) Save Replay GIF",
+        Text::ShareCodeLine => "Share Code: {}",
+        Text::Saved => "Saved {}",
+        Text::ScreenshotFailed => "Failed to save screenshot",
+        Text::ReplayFailed => "Failed to save replay",
+        Text::PhotoFailed => "Failed to save photo",
+        Text::PhotoHint => "PHOTO x{}  Arrows: Pan  +/-: Zoom  0: Reset  S: Save  Esc: Resume",
+        Text::StartGame => "Start Game",
+        Text::BackgroundStyle => "Background Style",
+        Text::PlayerStyle => "Player Style",
+        Text::ObstacleDistance => "Obstacle Distance",
+        Text::Difficulty => "Difficulty",
+        Text::Mode => "Mode",
+        Text::Leaderboard => "Leaderboard",
+        Text::Achievements => "Achievements",
+        Text::Options => "Options",
+        Text::TwoPlayers => "Two Players",
+        Text::EnterShareCode => "Enter Share Code",
+        Text::QuitGame => "Quit Game",
+        Text::MenuShortcuts => "(1/2/3) Background / Player / Obstacle",
+        Text::Stars => "Stars",
+        Text::Clouds => "Clouds",
+        Text::Mountains => "Mountains",
+        Text::Back => "Back",
+        Text::SelectBackground => "Select Background Style",
+        Text::SelectPlayer => "Select Player Style",
+        Text::CoinsTotal => "Coins: {}",
+        Text::LockedSkin => "[locked] {} - {} coins",
+        Text::UnlockFor => "Press Enter to unlock for {} coins",
+        Text::NeedMoreCoins => "Need {} more coins",
+        Text::CurrentSpaces => "Current: {} spaces",
+        Text::AdjustHint => "(Use Left/Right to adjust)",
+        Text::Pace => "Pace: ~{} pipes/min ({})",
+        Text::Relaxed => "Relaxed",
+        Text::Moderate => "Moderate",
+        Text::Frantic => "Frantic",
+        Text::NoScores => "No scores yet",
+        Text::PipesUnit => "pipes",
+        Text::SwitchBoardHint => "(Use Left/Right to switch board)",
+        Text::AchievementsCount => "Achievements ({}/{})",
+        Text::MusicVolume => "Music Volume",
+        Text::Lives => "Lives",
+        Text::FlapCooldown => "Flap Cooldown",
+        Text::PipeStyle => "Pipe Style",
+        Text::StartGrace => "Start Grace",
+        Text::Auto => "Auto",
+        Text::BackgroundScroll => "Background Scroll",
+        Text::Gravity => "Gravity",
+        Text::UpcomingPipes => "Upcoming Pipes",
+        Text::HighlightColor => "Highlight Color",
+        Text::MenuTextColor => "Menu Text Color",
+        Text::SpeedTrails => "Speed Trails",
+        Text::ReducedMotion => "Reduced Motion",
+        Text::Scoring => "Scoring",
+        Text::GapFeathering => "Gap Feathering",
+        Text::FlapAssist => "Flap Assist",
+        Text::NearPipeSlowdown => "Near-Pipe Slowdown",
+        Text::HudPanel => "HUD Panel",
+        Text::Flap => "Flap",
+        Text::SpriteScaling => "Sprite Scaling",
+        Text::IdealPath => "Ideal Path",
+        Text::Language => "Language",
+        Text::PipeSpacing => "Pipe Spacing",
+        Text::Varied => "Varied",
+        Text::Fixed => "Fixed",
+        Text::PassedPipes => "Passed Pipes",
+        Text::CheckpointReached => "Checkpoint!",
+        Text::CheckpointRespawn => "Back to the last checkpoint ({} retries left)",
+        Text::HudCheckpoint => "Checkpoint: {} pipes  Retries: {}",
+        Text::BackgroundBrightness => "Background Brightness",
+        Text::DimSprites => "Dim Player Too",
+        Text::SeedLine => "Seed: {}  (C) Copy",
+        Text::SeedCopied => "Seed copied to the clipboard",
+        Text::SeedCopyFailed => "Could not reach the clipboard, seed printed to the console",
+        Text::InputLogFailed => "Failed to save input log",
+        Text::GapCurve => "Gap size over the first {} points",
+        Text::SafeZone => "Gap Highlight",
+        Text::Foreground => "Foreground Layer",
+        Text::QuickRestart => "Quick Restart Key",
+        Text::SoundVolume => "Sound Volume",
+        Text::FlapKey => "Flap Key",
+        Text::FastFallKey => "Fast Fall Key",
+        Text::KeySpace => "Space",
+        Text::KeyUp => "Up",
+        Text::KeyDown => "Down",
+        Text::KeyShift => "Shift",
+        Text::KeyCtrl => "Ctrl",
+        Text::On => "On",
+        Text::Off => "Off",
+        Text::Shown => "Shown",
+        Text::Hidden => "Hidden",
+        Text::Classic => "Classic",
+        Text::HoldToClimb => "Hold to Climb",
+        Text::Smooth => "Smooth",
+        Text::PixelArt => "Pixel Art",
+        Text::ResetProgress => "Reset Progress",
+        Text::ReallyReset => "Really reset all progress? (Y/N)",
+        Text::ShareCodeHint => "(Enter) Play  (Esc) Back",
+        Text::ProfileOption => "Profile: {}",
+        Text::Profiles => "Profiles",
+        Text::NewProfile => "New Profile",
+        Text::ProfilesHint => "(Enter) Switch  (D) Delete  (Esc) Back",
+        Text::ReallyDeleteProfile => "Really delete profile {}? (Y/N)",
+        Text::ProfileNameHint => "(Enter) Create  (Esc) Back",
+        Text::ProfileNameInvalid => "Use 1-16 letters, digits or spaces",
+        Text::ProfileExists => "That profile already exists",
+        Text::CannotDeleteDefault => "The default profile cannot be deleted",
+        Text::Easy => "Easy",
+        Text::Normal => "Normal",
+        Text::Hard => "Hard",
+        Text::Adaptive => "Adaptive",
+        Text::Combo => "Combo",
+        Text::Sandbox => "Sandbox",
+        Text::Zen => "Zen",
+        Text::Marathon => "Marathon",
+        Text::Dragon => "Dragon",
+        Text::Bird => "Bird",
+        Text::Duck => "Duck",
+        Text::Yellow => "Yellow",
+        Text::White => "White",
+        Text::Cyan => "Cyan",
+        Text::Magenta => "Magenta",
+        Text::Orange => "Orange",
+        Text::Green => "Green",
+        Text::Gray => "Gray",
+        Text::Solid => "Solid",
+        Text::Shade => "Shade",
+        Text::Hash => "Hash",
+        Text::Brick => "Brick",
+        Text::Linear => "Linear",
+        Text::Quadratic => "Quadratic",
+        Text::Snappy => "Snappy",
+        Text::RightToLeft => "Right to Left",
+        Text::LeftToRight => "Left to Right",
+        Text::Vertical => "Vertical",
+        Text::Light => "Light",
+        Text::Full => "Full",
+        Text::Points => "Points",
+        Text::Time => "Time",
+        Text::CasualBoard => "Casual Leaderboard",
+        Text::HardcoreBoard => "Hardcore Leaderboard",
+        Text::CasualTimeBoard => "Casual Leaderboard (Time)",
+        Text::HardcoreTimeBoard => "Hardcore Leaderboard (Time)",
+        Text::ZenBoard => "Zen Leaderboard",
+        Text::AchievementFirstPipe => "First Flight",
+        Text::AchievementFirstPipeGoal => "Pass a pipe",
+        Text::AchievementPipes10 => "Getting the Hang of It",
+        Text::AchievementPipes10Goal => "Pass 10 pipes",
+        Text::AchievementPipes50 => "Frequent Flyer",
+        Text::AchievementPipes50Goal => "Pass 50 pipes",
+        Text::AchievementScore50 => "Half Century",
+        Text::AchievementScore50Goal => "Score 50",
+        Text::AchievementPerfect5 => "Smooth Operator",
+        Text::AchievementPerfect5Goal => "5 perfect passes in a row",
+        Text::AchievementCoins10 => "Pocket Money",
+        Text::AchievementCoins10Goal => "Collect 10 coins in one run",
+        Text::AchievementHardPipes20 => "Hard as Nails",
+        Text::AchievementHardPipes20Goal => "Pass 20 pipes on Hard",
     }
 }
 
-impl Player {
-    fn new(x: i32, y: i32) -> Self {
-        Self {
-            x,
-            y,
-            velocity: 0.0,
+fn spanish_text(key: Text) -> Option<&'static str> {
+    Some(match key {
+        Text::PressToFlap => "Pulsa {} para aletear",
+        Text::PhotoModeHint => "(P) Modo foto",
+        Text::SandboxHint => "SANDBOX - Pulsa Esc para volver al menú",
+        Text::HudLine => "Puntos: {} (x{} {})  Récord: {}  Tubos: {}  Monedas: {}  Tiempo: {}",
+        Text::HudPlayer => "Jugador {}",
+        Text::HudLives => "Vidas:",
+        Text::HudShield => "Escudo",
+        Text::HudSpeed => "Ritmo",
+        Text::HudCombo => "x{} ({} limpios)",
+        Text::AchievementUnlocked => "Logro desbloqueado: {}",
+        Text::TutorialFlap => "Pulsa {} para batir las alas",
+        Text::TutorialGaps => "Vuela por los huecos entre los tubos",
+        Text::TutorialAvoid => "No toques los tubos ni el suelo",
+        Text::TutorialDone => "¡Genial! Ahora vuelas solo",
+        Text::TutorialScore => "Puntos: {}  Tubos: {}",
+        Text::SkipTutorial => "(Esc) Saltar tutorial",
+        Text::DemoHint => "DEMO - Pulsa cualquier tecla",
+        Text::HotSeatScored => "El jugador 1 hizo {} puntos",
+        Text::HotSeatReady => "¡Jugador 2, prepárate!",
+        Text::PressSpaceToStart => "Pulsa ESPACIO para empezar",
+        Text::Player1Wins => "¡Gana el jugador 1!",
+        Text::Player2Wins => "¡Gana el jugador 2!",
+        Text::Tie => "¡Empate!",
+        Text::HotSeatResult => "Jugador {}: {}",
+        Text::PlayAgain => "(P) Jugar otra vez",
+        Text::MainMenuKey => "(M) Menú principal",
+        Text::YouAreDead => "¡Has muerto!",
+        Text::FinalScore => "Puntuación final: {}  Tubos: {}  Tiempo: {}  Monedas: +{} ({})",
+        Text::HighScore => "Récord: {}",
+        Text::LastAndBest => "Última: {}  Mejor: {}",
+        Text::WindowTooSmall => "Ventana demasiado pequeña",
+        Text::WindowMinSize => "Agrándala al menos a {} x {} píxeles",
+        Text::RecordedOn => "Registrado en: {}",
+        Text::PlayAgainRetry => "(P) Jugar otra vez  (T) Repetir recorrido",
+        Text::ReallyQuit => "¿Salir de verdad? (Y/N)",
+        Text::QuitGameKey => "(Q) Salir del juego",
+        Text::SaveScreenshotKey => "(S) Guardar captura",
+        Text::RewindKeys => "(B) Rebobinar 2 segundos  (G) Guardar GIF",
+        Text::ShareCodeLine => "Código para compartir: {}",
+        Text::Saved => "Guardado {}",
+        Text::ScreenshotFailed => "No se pudo guardar la captura",
+        Text::ReplayFailed => "No se pudo guardar la repetición",
+        Text::PhotoFailed => "No se pudo guardar la foto",
+        Text::PhotoHint => "FOTO x{}  Flechas: Mover  +/-: Zoom  0: Restablecer  S: Guardar  Esc: Volver",
+        Text::StartGame => "Empezar",
+        Text::BackgroundStyle => "Estilo de fondo",
+        Text::PlayerStyle => "Estilo de jugador",
+        Text::ObstacleDistance => "Distancia entre tubos",
+        Text::Difficulty => "Dificultad",
+        Text::Mode => "Modo",
+        Text::Leaderboard => "Clasificación",
+        Text::Achievements => "Logros",
+        Text::Options => "Opciones",
+        Text::TwoPlayers => "Dos jugadores",
+        Text::EnterShareCode => "Introducir código",
+        Text::QuitGame => "Salir",
+        Text::MenuShortcuts => "(1/2/3) Fondo / Jugador / Tubos",
+        Text::Stars => "Estrellas",
+        Text::Clouds => "Nubes",
+        Text::Mountains => "Montañas",
+        Text::Back => "Volver",
+        Text::SelectBackground => "Elige el fondo",
+        Text::SelectPlayer => "Elige el jugador",
+        Text::CoinsTotal => "Monedas: {}",
+        Text::LockedSkin => "[bloqueado] {} - {} monedas",
+        Text::UnlockFor => "Pulsa Enter para desbloquear por {} monedas",
+        Text::NeedMoreCoins => "Faltan {} monedas",
+        Text::CurrentSpaces => "Actual: {} espacios",
+        Text::AdjustHint => "(Izquierda/Derecha para ajustar)",
+        Text::Pace => "Ritmo: ~{} tubos/min ({})",
+        Text::Relaxed => "Tranquilo",
+        Text::Moderate => "Moderado",
+        Text::Frantic => "Frenético",
+        Text::NoScores => "Aún no hay puntuaciones",
+        Text::PipesUnit => "tubos",
+        Text::SwitchBoardHint => "(Izquierda/Derecha para cambiar de tabla)",
+        Text::AchievementsCount => "Logros ({}/{})",
+        Text::MusicVolume => "Volumen de música",
+        Text::Lives => "Vidas",
+        Text::FlapCooldown => "Espera entre aleteos",
+        Text::PipeStyle => "Estilo de tubos",
+        Text::StartGrace => "Gracia inicial",
+        Text::BackgroundScroll => "Desplazamiento del fondo",
+        Text::Gravity => "Gravedad",
+        Text::UpcomingPipes => "Tubos próximos",
+        Text::HighlightColor => "Color de resaltado",
+        Text::MenuTextColor => "Color del texto",
+        Text::SpeedTrails => "Estelas de velocidad",
+        Text::ReducedMotion => "Movimiento reducido",
+        Text::Scoring => "Puntuación",
+        Text::GapFeathering => "Bordes suaves",
+        Text::FlapAssist => "Ayuda al aleteo",
+        Text::NearPipeSlowdown => "Cámara lenta en tubos",
+        Text::HudPanel => "Panel del HUD",
+        Text::Flap => "Aleteo",
+        Text::SpriteScaling => "Escalado de sprites",
+        Text::IdealPath => "Ruta ideal",
+        Text::Language => "Idioma",
+        Text::PipeSpacing => "Separación de tubos",
+        Text::Varied => "Variada",
+        Text::Fixed => "Fija",
+        Text::PassedPipes => "Tubos superados",
+        Text::CheckpointReached => "¡Punto de control!",
+        Text::CheckpointRespawn => "De vuelta al último punto de control (quedan {})",
+        Text::HudCheckpoint => "Control: {} tubos  Reintentos: {}",
+        Text::BackgroundBrightness => "Brillo del fondo",
+        Text::DimSprites => "Oscurecer también al jugador",
+        Text::SeedLine => "Semilla: {}  (C) Copiar",
+        Text::SeedCopied => "Semilla copiada al portapapeles",
+        Text::SeedCopyFailed => "No se pudo usar el portapapeles, semilla escrita en la consola",
+        Text::InputLogFailed => "No se pudo guardar el registro de entradas",
+        Text::GapCurve => "Tamaño del hueco en los primeros {} puntos",
+        Text::SafeZone => "Resaltar hueco",
+        Text::Foreground => "Capa frontal",
+        Text::QuickRestart => "Tecla de reinicio rápido",
+        Text::SoundVolume => "Volumen de efectos",
+        Text::FlapKey => "Tecla de aleteo",
+        Text::FastFallKey => "Tecla de caída rápida",
+        Text::KeySpace => "Espacio",
+        Text::KeyUp => "Arriba",
+        Text::KeyDown => "Abajo",
+        Text::KeyShift => "Mayús",
+        Text::On => "Sí",
+        Text::Off => "No",
+        Text::Shown => "Visible",
+        Text::Hidden => "Oculto",
+        Text::Classic => "Clásico",
+        Text::HoldToClimb => "Mantener para subir",
+        Text::Smooth => "Suave",
+        Text::PixelArt => "Pixel art",
+        Text::ResetProgress => "Borrar progreso",
+        Text::ReallyReset => "¿Borrar todo el progreso? (Y/N)",
+        Text::ShareCodeHint => "(Enter) Jugar  (Esc) Volver",
+        Text::ProfileOption => "Perfil: {}",
+        Text::Profiles => "Perfiles",
+        Text::NewProfile => "Nuevo perfil",
+        Text::ProfilesHint => "(Enter) Cambiar  (D) Borrar  (Esc) Volver",
+        Text::ReallyDeleteProfile => "¿Borrar el perfil {}? (Y/N)",
+        Text::ProfileNameHint => "(Enter) Crear  (Esc) Volver",
+        Text::ProfileNameInvalid => "Usa de 1 a 16 letras, números o espacios",
+        Text::ProfileExists => "Ese perfil ya existe",
+        Text::CannotDeleteDefault => "El perfil por defecto no se puede borrar",
+        Text::Easy => "Fácil",
+        Text::Hard => "Difícil",
+        Text::Adaptive => "Adaptativa",
+        Text::Marathon => "Maratón",
+        Text::Dragon => "Dragón",
+        Text::Bird => "Pájaro",
+        Text::Duck => "Pato",
+        Text::Yellow => "Amarillo",
+        Text::White => "Blanco",
+        Text::Cyan => "Cian",
+        Text::Orange => "Naranja",
+        Text::Green => "Verde",
+        Text::Gray => "Gris",
+        Text::Solid => "Sólido",
+        Text::Shade => "Sombreado",
+        Text::Hash => "Almohadilla",
+        Text::Brick => "Ladrillo",
+        Text::Linear => "Lineal",
+        Text::Quadratic => "Cuadrática",
+        Text::Snappy => "Brusca",
+        Text::RightToLeft => "De derecha a izquierda",
+        Text::LeftToRight => "De izquierda a derecha",
+        Text::Light => "Ligera",
+        Text::Full => "Completa",
+        Text::Points => "Puntos",
+        Text::Time => "Tiempo",
+        Text::CasualBoard => "Clasificación casual",
+        Text::HardcoreBoard => "Clasificación hardcore",
+        Text::CasualTimeBoard => "Clasificación casual (tiempo)",
+        Text::HardcoreTimeBoard => "Clasificación hardcore (tiempo)",
+        Text::ZenBoard => "Clasificación zen",
+        Text::AchievementFirstPipe => "Primer vuelo",
+        Text::AchievementFirstPipeGoal => "Supera un tubo",
+        Text::AchievementPipes10 => "Le vas pillando el truco",
+        Text::AchievementPipes10Goal => "Supera 10 tubos",
+        Text::AchievementPipes50 => "Viajero frecuente",
+        Text::AchievementPipes50Goal => "Supera 50 tubos",
+        Text::AchievementScore50 => "Medio centenar",
+        Text::AchievementScore50Goal => "Consigue 50 puntos",
+        Text::AchievementPerfect5 => "Pulso firme",
+        Text::AchievementPerfect5Goal => "5 pasos perfectos seguidos",
+        Text::AchievementCoins10 => "Calderilla",
+        Text::AchievementCoins10Goal => "Recoge 10 monedas en una partida",
+        Text::AchievementHardPipes20 => "Duro de pelar",
+        Text::AchievementHardPipes20Goal => "Supera 20 tubos en Difícil",
+        // 和英文一样的词（比如 Auto）不用重复写
+        _ => return None,
+    })
+}
+
+// 把模板里的 {} 依次换成给定的值
+fn fill(template: &str, values: &[&dyn std::fmt::Display]) -> String {
+    let mut result = String::new();
+    let mut values = values.iter();
+    let mut rest = template;
+    while let Some(index) = rest.find("{}") {
+        result.push_str(&rest[..index]);
+        if let Some(value) = values.next() {
+            result.push_str(&value.to_string());
         }
+        rest = &rest[index + 2..];
     }
+    result.push_str(rest);
+    result
+}
 
-fn render(&mut self, ctx: &mut BTerm, texture: &Texture, style: &PlayerStyle) {
-    let image = match style {
-        PlayerStyle::Dragon => &texture.player_dragon,
-        PlayerStyle::Bird => &texture.player_bird,
-        PlayerStyle::Duck => &texture.player_duck,
-    };
-
-    for y in 0..DEFAULT_PARAMETERS.player_height {
-        for x in 0..DEFAULT_PARAMETERS.player_width {
-            let screen_x = self.x + x;
-            let screen_y = self.y + y;
+impl Leaderboard {
+    const MAX_ENTRIES: usize = 10;
 
-            if screen_x < 0 || screen_x >= DEFAULT_PARAMETERS.screen_width ||
-               screen_y < 0 || screen_y >= DEFAULT_PARAMETERS.screen_height {
-                continue;
-            }
+    // 每行“分数 管道数 毫秒数 难度”，读不到文件就是空榜
+    fn empty(kind: LeaderboardKind) -> Self {
+        Self { kind, entries: Vec::new() }
+    }
 
-            let pixel = image.get_pixel(x as u32, y as u32);
-            let alpha = pixel[3];
+    fn load(kind: LeaderboardKind, storage: &Storage) -> Self {
+        let mut entries: Vec<LeaderboardEntry> = match storage.read(kind.file_name()) {
+            Some(content) => content.lines().filter_map(LeaderboardEntry::parse).collect(),
+            None => Vec::new(),
+        };
+        entries.sort_by_key(|e| std::cmp::Reverse(kind.rank_value(e)));
+        entries.truncate(Self::MAX_ENTRIES);
+        Self { kind, entries }
+    }
 
-            if alpha == 0 {
-                continue; // 透明像素不渲染
-            }
+    // 返回成绩是否上榜
+    fn submit(&mut self, entry: LeaderboardEntry) -> bool {
+        let value = self.kind.rank_value(&entry);
+        if value <= 0 {
+            return false;
+        }
+        let index = self.entries.iter().position(|e| value > self.kind.rank_value(e)).unwrap_or(self.entries.len());
+        if index >= Self::MAX_ENTRIES {
+            return false;
+        }
+        self.entries.insert(index, entry);
+        self.entries.truncate(Self::MAX_ENTRIES);
+        true
+    }
 
-            let color = RGB::from_u8(pixel[0], pixel[1], pixel[2]);
-            ctx.set(screen_x, screen_y, BLACK, color, to_cp437(' '));
+    fn remove(&mut self, entry: LeaderboardEntry) {
+        if let Some(index) = self.entries.iter().position(|e| *e == entry) {
+            self.entries.remove(index);
         }
     }
+
+    fn save(&self, storage: &Storage) {
+        let content: Vec<String> = self.entries.iter().map(|e| {
+            let difficulty = e.difficulty.map_or("-", |difficulty| difficulty.name());
+            format!("{} {} {} {}", e.score, e.pipes, e.time_ms, difficulty)
+        }).collect();
+        storage.write(self.kind.file_name(), &content.join("\n"));
+    }
+}
+
+impl LeaderboardEntry {
+    // 旧版文件每行只有分数，那时分数就等于管道数；没有记录时间的记为 0，没有记录难度的为 None
+    fn parse(line: &str) -> Option<Self> {
+        let mut parts = line.split_whitespace();
+        let score = parts.next()?.parse().ok()?;
+        let pipes = match parts.next() {
+            Some(pipes) => pipes.parse().ok()?,
+            None => score,
+        };
+        let time_ms = match parts.next() {
+            Some(time_ms) => time_ms.parse().ok()?,
+            None => 0,
+        };
+        let difficulty = parts.next().and_then(Difficulty::from_name);
+        Some(Self { score, pipes, time_ms, difficulty })
+    }
 }
 
-    fn gravity_to_move(&mut self) {
-        if self.velocity < 2.0 {
-            self.velocity += 0.2;
+impl Storage {
+    fn new(enabled: bool, profile: &str) -> Self {
+        Self { enabled, profile: profile.to_string() }
+    }
+
+    // 存档文件在磁盘上的实际位置
+    fn path(&self, file: &str) -> PathBuf {
+        if self.profile == DEFAULT_PROFILE {
+            PathBuf::from(file)
+        } else {
+            Path::new(PROFILES_DIR).join(&self.profile).join(file)
         }
-        self.y += self.velocity as i32;
-        
+    }
 
-        if self.y < 0 {
-            self.y = 0;
+    fn read(&self, path: &str) -> Option<String> {
+        if !self.enabled {
+            return None;
+        }
+        let path = self.path(path);
+        // 文件不存在是正常情况（例如第一次运行），其他读取错误打印出来方便排查
+        match fs::read_to_string(&path) {
+            Ok(content) => Some(content),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => None,
+            Err(error) => {
+                eprintln!("Warning: could not read {}: {}", path.display(), error);
+                None
+            }
         }
     }
 
-    fn flap(&mut self) {
-        self.velocity = -2.5;
+    // 写失败（例如只读文件系统）时直接忽略
+    fn write(&self, path: &str, content: &str) {
+        if self.enabled {
+            let path = self.path(path);
+            if let Some(parent) = path.parent() {
+                let _ = fs::create_dir_all(parent);
+            }
+            let _ = fs::write(path, content);
+        }
     }
-}
 
-impl Obstacle {
-    fn new(x: i32, score: i32) -> Self {
-        let mut random = RandomNumberGenerator::new();
-        Self {
-            x: x as f32,
-            gap_y: random.range(30, 60),
-            size: i32::max(20, 40 - score / 2),
-            scored: false, // 初始状态未得分
+    // 文件本来就不存在时什么也不做
+    fn remove(&self, path: &str) {
+        if !self.enabled {
+            return;
+        }
+        let path = self.path(path);
+        match fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+            Err(error) => eprintln!("Warning: could not remove {}: {}", path.display(), error),
         }
     }
 
-    fn render(&mut self, ctx: &mut BTerm) {
-        self.x -= DEFAULT_PARAMETERS.obstacle_speed;
-        let half_size = self.size / 2;
+    fn exists(&self, path: &str) -> bool {
+        self.enabled && self.path(path).exists()
+    }
 
-        // 绘制上半部分障碍物
-        for y in 0..self.gap_y - half_size {
-            ctx.set(self.x as i32, y, RED, YELLOW, to_cp437('|'));
+    // 所有存档的名字，default 在最前，其余按名字排序；当前存档即使还没有目录也会列出
+    fn profiles(&self) -> Vec<String> {
+        let mut names: Vec<String> = if self.enabled {
+            fs::read_dir(PROFILES_DIR)
+                .map(|entries| {
+                    entries
+                        .filter_map(|entry| entry.ok())
+                        .filter(|entry| entry.path().is_dir())
+                        .filter_map(|entry| entry.file_name().into_string().ok())
+                        .filter(|name| valid_profile_name(name) && name != DEFAULT_PROFILE)
+                        .collect()
+                })
+                .unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        if self.profile != DEFAULT_PROFILE && !names.contains(&self.profile) {
+            names.push(self.profile.clone());
         }
+        names.sort();
+        names.insert(0, DEFAULT_PROFILE.to_string());
+        names
+    }
 
-        // 绘制下半部分障碍物
-        for y in self.gap_y + half_size..DEFAULT_PARAMETERS.screen_height {
-            ctx.set(self.x as i32, y, RED, YELLOW, to_cp437('|'));
+    // 新建存档就是建一个空目录，这样还没玩过也能出现在列表里
+    fn create_profile(&self, name: &str) {
+        if self.enabled {
+            let _ = fs::create_dir_all(Path::new(PROFILES_DIR).join(name));
         }
     }
 
-    fn hit_obstacle(&self, player: &Player) -> bool {
-        let half_size = self.size / 2;
-        let player_left_gap = player.x < self.x as i32;
-        let player_right_gap = (player.x +DEFAULT_PARAMETERS.player_width) > self.x as i32;
-        let player_above_gap = player.y < self.gap_y - half_size;
-        let player_below_gap = (player.y +DEFAULT_PARAMETERS.player_height) > self.gap_y + half_size;
-        (player_left_gap && player_right_gap) && (player_above_gap || player_below_gap)
+    // default 存档的文件散在当前目录里，不能整个删掉
+    fn delete_profile(&self, name: &str) {
+        if !self.enabled || name == DEFAULT_PROFILE {
+            return;
+        }
+        let path = Path::new(PROFILES_DIR).join(name);
+        match fs::remove_dir_all(&path) {
+            Ok(()) => {}
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+            Err(error) => eprintln!("Warning: could not remove {}: {}", path.display(), error),
+        }
     }
 }
 
+// 存档名会用作目录名：1 到 16 个字母、数字或空格，首尾不能是空格
+fn valid_profile_name(name: &str) -> bool {
+    (1..=16).contains(&name.chars().count())
+        && name.trim() == name
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == ' ')
+}
+
 impl Texture {
-    fn new() -> Self {
+    // 第 frame 帧，超出帧数时循环播放
+    fn player_image(&self, style: &PlayerStyle, frame: usize) -> &image::DynamicImage {
+        let frames = match style {
+            PlayerStyle::Dragon => &self.player_dragon,
+            PlayerStyle::Bird => &self.player_bird,
+            PlayerStyle::Duck => &self.player_duck,
+        };
+        &frames[frame % frames.len()]
+    }
+
+    fn background_image(&self, style: &BackgroundStyle, frame: usize) -> &image::DynamicImage {
+        let frames = match style {
+            BackgroundStyle::Stars => &self.background_stars,
+            BackgroundStyle::Clouds => &self.background_clouds,
+            BackgroundStyle::Mountains => &self.background_mountains,
+        };
+        &frames[frame % frames.len()]
+    }
+
+    fn new() -> Result<Self, String> {
         // 玩家贴图
-        let player_dragon = image::load_from_memory(include_bytes!("player/dragon.png"))
-            .expect("Failed to load dragon image");
-        let player_bird = image::load_from_memory(include_bytes!("player/bird.png"))
-            .expect("Failed to load bird image");
-        let player_duck = image::load_from_memory(include_bytes!("player/duck.png"))
-            .expect("Failed to load duck image");
+        let player_dragon = load_image("player/dragon.png", include_bytes!("player/dragon.png"))?;
+        let player_bird = load_image("player/bird.png", include_bytes!("player/bird.png"))?;
+        let player_duck = load_image("player/duck.png", include_bytes!("player/duck.png"))?;
 
         // 背景贴图
-        let background_stars = image::load_from_memory(include_bytes!("background/stars.png"))
-            .expect("Failed to load stars background image");
-        let background_clouds = image::load_from_memory(include_bytes!("background/clouds.png"))
-            .expect("Failed to load clouds background image");
-        let background_mountains = image::load_from_memory(include_bytes!("background/mountains.png"))
-            .expect("Failed to load mountains background image");
+        let background_stars = load_image("background/stars.png", include_bytes!("background/stars.png"))?;
+        let background_clouds = load_image("background/clouds.png", include_bytes!("background/clouds.png"))?;
+        let background_mountains = load_image("background/mountains.png", include_bytes!("background/mountains.png"))?;
 
         // 菜单标题
         let menu_title = vec![
@@ -591,32 +5654,814 @@ impl Texture {
             (23, 7, to_cp437('D')), (25, 7, to_cp437('R')), (27, 7, to_cp437('A')),
             (29, 7, to_cp437('G')), (31, 7, to_cp437('O')), (33, 7, to_cp437('N')),
         ];
-        Self {
-            player_dragon,
-            player_bird,
-            player_duck,
-            background_stars,
-            background_clouds,
-            background_mountains,
+
+        // 前景贴图可选：文件不存在时静默跳过，解码失败时打印警告后照样启动
+        let foreground = fs::read(FOREGROUND_FILE).ok().and_then(|bytes| {
+            load_image(FOREGROUND_FILE, &bytes).map_err(|error| eprintln!("Warning: {}", error)).ok()
+        });
+        Ok(Self {
+            player_dragon: vec![player_dragon],
+            player_bird: vec![player_bird],
+            player_duck: vec![player_duck],
+            background_stars: vec![background_stars],
+            background_clouds: vec![background_clouds],
+            background_mountains: vec![background_mountains],
             menu_title,
+            foreground,
+        })
+    }
+}
+
+fn load_image(name: &str, bytes: &[u8]) -> Result<image::DynamicImage, String> {
+    image::load_from_memory(bytes).map_err(|error| format!("Failed to load image {}: {}", name, error))
+}
+
+impl MusicTrack {
+    fn file_name(&self) -> &'static str {
+        match self {
+            MusicTrack::Menu => "music/menu.ogg",
+            MusicTrack::Gameplay => "music/gameplay.ogg",
+        }
+    }
+
+    // 预先混好的强度层，和主曲目等长，叠在上面播放；不改变播放速度，不会有变调
+    fn layer_file_names(&self) -> &'static [&'static str] {
+        match self {
+            MusicTrack::Menu => &[],
+            MusicTrack::Gameplay => &["music/gameplay_layer1.ogg", "music/gameplay_layer2.ogg"],
+        }
+    }
+}
+
+impl Audio {
+    // 没有声卡时静默运行，不影响游戏
+    fn new() -> Self {
+        match OutputStream::try_default() {
+            Ok((stream, handle)) => Self {
+                _stream: Some(stream),
+                handle: Some(handle),
+                music: None,
+                layers: Vec::new(),
+                current_track: None,
+                flap_sound: Self::load_sound(FLAP_SOUND_FILE),
+            },
+            Err(_) => Self {
+                _stream: None,
+                handle: None,
+                music: None,
+                layers: Vec::new(),
+                current_track: None,
+                flap_sound: None,
+            },
+        }
+    }
+
+    // 播放指定曲目（循环），如果已经在播放同一首则只更新音量
+    fn play_music(&mut self, track: MusicTrack, volume: f32) {
+        if self.current_track == Some(track) {
+            if let Some(sink) = &self.music {
+                sink.set_volume(volume);
+            }
+            return;
+        }
+
+        self.stop_music();
+        self.current_track = Some(track);
+
+        let Some(handle) = &self.handle else {
+            return;
+        };
+        let Some(sink) = Self::open_loop(handle, track.file_name()) else {
+            return;
+        };
+        sink.set_volume(volume);
+        self.music = Some(sink);
+        // 强度层先静音播放，和主曲目同时开始才能对齐节拍；缺哪层就少哪层
+        self.layers = track.layer_file_names()
+            .iter()
+            .filter_map(|name| Self::open_loop(handle, name))
+            .inspect(|layer| layer.set_volume(0.0))
+            .collect();
+    }
+
+    // 打开一个无限循环的曲目；文件不存在或无法解码时返回 None，保持安静
+    fn open_loop(handle: &OutputStreamHandle, file_name: &str) -> Option<Sink> {
+        let file = File::open(file_name).ok()?;
+        let source = Decoder::new(BufReader::new(file)).ok()?;
+        let sink = Sink::try_new(handle).ok()?;
+        // 先缓冲再无限循环，循环衔接处不会重新解码产生停顿
+        sink.append(source.buffered().repeat_infinite());
+        Some(sink)
+    }
+
+    // 音效文件不存在或无法解码时返回 None，对应的音效不播放
+    fn load_sound(file_name: &str) -> Option<Buffered<Decoder<BufReader<File>>>> {
+        let file = File::open(file_name).ok()?;
+        Some(Decoder::new(BufReader::new(file)).ok()?.buffered())
+    }
+
+    // 扇翅膀的音效，rate 同时改变播放速度和音高，1.0 为原样
+    fn play_flap(&self, rate: f32, volume: f32) {
+        let (Some(handle), Some(sound)) = (&self.handle, &self.flap_sound) else {
+            return;
+        };
+        if volume <= 0.0 {
+            return;
+        }
+        if let Ok(sink) = Sink::try_new(handle) {
+            sink.set_volume(volume);
+            sink.append(sound.clone().speed(rate));
+            sink.detach(); // 放完自己结束，不用一直持有
+        }
+    }
+
+    // 第 n 层（从 0 数）的音量在强度 n 到 n+1 之间从 0 升到满
+    fn set_intensity(&self, intensity: f32, volume: f32) {
+        for (i, layer) in self.layers.iter().enumerate() {
+            layer.set_volume(volume * (intensity - i as f32).clamp(0.0, 1.0));
+        }
+    }
+
+    fn stop_music(&mut self) {
+        if let Some(sink) = self.music.take() {
+            sink.stop();
         }
+        for layer in self.layers.drain(..) {
+            layer.stop();
+        }
+        self.current_track = None;
     }
 }
 
 impl GameState for State {
     fn tick(&mut self, ctx: &mut BTerm) {
+        begin_frame(ctx);
+        // 窗口太小时游戏整个停住，只显示提示；窗口拉大后下一帧自动接着来
+        if window_too_small(ctx) {
+            self.render_window_too_small(ctx);
+            return;
+        }
+        self.animation_clock_ms += frame_time_ms(ctx);
         match self.mode {
             GameMode::Menu => self.main_menu(ctx),
             GameMode::Playing => self.playing(ctx),
             GameMode::End => self.end(ctx),
+            GameMode::Demo => self.demo(ctx),
+            GameMode::Tutorial => self.tutorial(ctx),
+            GameMode::HotSeat => self.hot_seat_screen(ctx),
+            GameMode::Photo => self.photo_mode(ctx),
+        }
+
+        if ctx.quitting {
+            // 进程马上就要结束，等最后一局的成绩发出去（最多等到请求超时）
+            if let Some(upload) = self.submit_final_score() {
+                let _ = upload.join();
+            }
+            self.audio.stop_music();
+        } else {
+            self.update_music();
+        }
+    }
+}
+
+impl LaunchOptions {
+    const USAGE: &'static str = "Usage: game [--play] [--style dragon|bird|duck] [--bg stars|clouds|mountains] [--seed N] [--letterbox] [--portrait] [--no-save] [--name NAME] [--submit-url URL] [--profile NAME]";
+
+    // 解析命令行参数，返回 Err 时附带错误说明
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self {
+            play: false,
+            player_style: None,
+            background_style: None,
+            seed: None,
+            letterbox: false,
+            portrait: false,
+            no_save: false,
+            player_name: None,
+            submit_url: None,
+            profile: None,
+        };
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--play" => options.play = true,
+                "--letterbox" => options.letterbox = true,
+                "--portrait" => options.portrait = true,
+                "--no-save" => options.no_save = true,
+                "--name" => options.player_name = Some(args.next().ok_or("--name needs a value")?),
+                "--submit-url" => options.submit_url = Some(args.next().ok_or("--submit-url needs a value")?),
+                "--style" => {
+                    let value = args.next().ok_or("--style needs a value")?;
+                    options.player_style = Some(PlayerStyle::from_name(&value)
+                        .ok_or(format!("Unknown player style: {}", value))?);
+                }
+                "--bg" => {
+                    let value = args.next().ok_or("--bg needs a value")?;
+                    options.background_style = Some(BackgroundStyle::from_name(&value)
+                        .ok_or(format!("Unknown background style: {}", value))?);
+                }
+                "--profile" => {
+                    let value = args.next().ok_or("--profile needs a value")?;
+                    if !valid_profile_name(&value) {
+                        return Err(format!("Invalid profile name: {}", value));
+                    }
+                    options.profile = Some(value);
+                }
+                "--seed" => {
+                    let value = args.next().ok_or("--seed needs a value")?;
+                    options.seed = Some(value.parse().map_err(|_| format!("Invalid seed: {}", value))?);
+                }
+                _ => return Err(format!("Unknown argument: {}", arg)),
+            }
+        }
+        Ok(options)
+    }
+
+    fn apply(self, state: &mut State) {
+        // 命令行也不能绕过金币解锁：没解锁的皮肤保留原来的选择并给出提示
+        if let Some(style) = self.player_style {
+            if state.unlocked_skins.contains(&style) {
+                state.settings.player_style = style;
+            } else {
+                eprintln!(
+                    "Warning: style {} is locked ({} coins), keeping {}",
+                    style.name(),
+                    style.unlock_cost(),
+                    state.settings.player_style.name()
+                );
+            }
+        }
+        if let Some(style) = self.background_style {
+            state.settings.background_style = style;
+        }
+        state.next_seed = self.seed;
+        if let Some(name) = self.player_name {
+            state.settings.player_name = name;
+        }
+        state.settings.submit_url = self.submit_url;
+        // 没指定存档又有不止一个存档时，先让玩家选
+        if self.play {
+            state.restart();
+        } else if self.profile.is_none() && state.storage.profiles().len() > 1 {
+            state.open_profiles_menu();
+        } else if !state.storage.exists(TUTORIAL_MARKER) {
+            state.start_tutorial();
         }
     }
 }
 
 fn main() -> BError {
-    let context = BTermBuilder::simple(DEFAULT_PARAMETERS.screen_width, DEFAULT_PARAMETERS.screen_height)?
-        .with_tile_dimensions(10,10)   
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", LaunchOptions::USAGE);
+        return Ok(());
+    }
+    // 参数有误时打印用法后退出，而不是 panic
+    let launch_options = match LaunchOptions::parse(args.into_iter()) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            eprintln!("{}", LaunchOptions::USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(message) = DEFAULT_PARAMETERS.validate() {
+        eprintln!("Error: {}", message);
+        std::process::exit(1);
+    }
+
+    let profile = launch_options.profile.as_deref().unwrap_or(DEFAULT_PROFILE);
+    let mut state = match State::new(Storage::new(!launch_options.no_save, profile)) {
+        Ok(state) => state,
+        Err(message) => {
+            eprintln!("Error: {}", message);
+            std::process::exit(1);
+        }
+    };
+    // 竖屏启动时窗口宽高对调，并且一定要让控制台跟着窗口变，否则画面会被压扁
+    let letterbox = launch_options.letterbox || launch_options.portrait;
+    let (window_width, window_height) = if launch_options.portrait {
+        (DEFAULT_PARAMETERS.screen_height, DEFAULT_PARAMETERS.screen_width)
+    } else {
+        (DEFAULT_PARAMETERS.screen_width, DEFAULT_PARAMETERS.screen_height)
+    };
+    launch_options.apply(&mut state);
+
+    // 控制台 0 只画边框色；游戏画在稀疏控制台 1 上，这样平移后露出的边缘是边框色。
+    // 开启 --letterbox 时控制台随窗口变大，游戏区保持原尺寸居中，而不是被拉伸
+    let context = BTermBuilder::simple(window_width, window_height)?
+        .with_tile_dimensions(DEFAULT_PARAMETERS.tile_pixels, DEFAULT_PARAMETERS.tile_pixels)
+        .with_sparse_console(DEFAULT_PARAMETERS.screen_width, DEFAULT_PARAMETERS.screen_height, "terminal8x8.png")
+        .with_automatic_console_resize(letterbox)
         .with_title("Flappy Animals")
         .build()?;
-    main_loop(context, State::new())
-}
\ No newline at end of file
+    main_loop(context, state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEED: u64 = 42;
+
+    // 不读写存档，也不需要声卡
+    fn test_state() -> State {
+        State::new(Storage::new(false, DEFAULT_PROFILE)).expect("textures are embedded")
+    }
+
+    // 模拟这一局玩过一阵：分数、位置、随机数、各种缓冲都变了
+    fn play_a_little(state: &mut State) {
+        state.run.score = 7;
+        state.run.player.y += 5;
+        state.run.player.velocity = 1.5;
+        state.run.rng.next_u64();
+        for obstacle in &mut state.run.obstacle_list {
+            obstacle.x -= 10.0;
+            obstacle.scored = true;
+        }
+        let snapshot = state.snapshot();
+        state.run.rewind_buffer.push_back(snapshot);
+        state.run.toasts.push_back("toast".to_string());
+        state.run.time_ms = 1234.0;
+    }
+
+    #[test]
+    fn fresh_game_is_deterministic() {
+        let settings = test_state().settings;
+        let mut first = RunState::fresh_game(&settings, SEED, 10, 0.0);
+        let mut second = RunState::fresh_game(&settings, SEED, 10, 0.0);
+        assert_eq!(first.player, second.player);
+        assert_eq!(first.distance, second.distance);
+        assert_eq!(first.rng.next_u64(), second.rng.next_u64());
+    }
+
+    #[test]
+    fn consecutive_runs_start_from_identical_state() {
+        let mut state = test_state();
+        state.next_seed = Some(SEED);
+        state.restart();
+        let player = state.run.player.clone();
+        let obstacles = state.run.obstacle_list.clone();
+        let mut rng = state.run.rng.clone();
+
+        play_a_little(&mut state);
+        state.next_seed = Some(SEED);
+        state.restart();
+
+        assert_eq!(state.run.player, player);
+        assert_eq!(state.run.obstacle_list, obstacles);
+        assert_eq!(state.run.rng.next_u64(), rng.next_u64());
+        assert_eq!(state.run.score, 0);
+        assert_eq!(state.run.time_ms, 0.0);
+        assert!(state.run.rewind_buffer.is_empty());
+        assert!(state.run.input_log.is_empty());
+        assert!(state.run.toasts.is_empty());
+        assert!(state.run.coin_list.is_empty());
+    }
+
+    // 7x7 的贴图，每个像素的红、绿分量记下它的坐标
+    fn coordinate_sprite() -> image::DynamicImage {
+        image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(7, 7, |x, y| {
+            image::Rgba([x as u8 * 40, y as u8 * 40, 100, 255])
+        }))
+    }
+
+    fn source(x: u8, y: u8) -> image::Rgba<u8> {
+        image::Rgba([x * 40, y * 40, 100, 255])
+    }
+
+    #[test]
+    fn sprite_scales_7x7_to_14x14() {
+        assert_eq!((DEFAULT_PARAMETERS.player_width, DEFAULT_PARAMETERS.player_height), (14, 14));
+        let sprite = coordinate_sprite();
+        // 每个源像素正好铺 2x2 格
+        assert_eq!(sample_sprite(&sprite, 0, 0, false, false), source(0, 0));
+        assert_eq!(sample_sprite(&sprite, 13, 0, false, false), source(6, 0));
+        assert_eq!(sample_sprite(&sprite, 0, 13, false, false), source(0, 6));
+        assert_eq!(sample_sprite(&sprite, 13, 13, false, false), source(6, 6));
+        assert_eq!(sample_sprite(&sprite, 6, 6, false, false), source(3, 3));
+        assert_eq!(sample_sprite(&sprite, 7, 7, false, false), source(3, 3));
+        assert_eq!(sample_sprite(&sprite, 8, 8, false, false), source(4, 4));
+    }
+
+    #[test]
+    fn mirrored_sprite_reads_flipped_column() {
+        let sprite = coordinate_sprite();
+        assert_eq!(sample_sprite(&sprite, 0, 0, true, false), source(6, 0));
+        assert_eq!(sample_sprite(&sprite, 13, 13, true, false), source(0, 6));
+        assert_eq!(sample_sprite(&sprite, 7, 7, true, false), source(3, 3));
+        assert_eq!(sample_sprite(&sprite, 4, 9, true, false), source(4, 4));
+    }
+
+    #[test]
+    fn smooth_sprite_keeps_corners() {
+        let sprite = coordinate_sprite();
+        assert_eq!(sample_sprite(&sprite, 0, 0, false, true), source(0, 0));
+        assert_eq!(sample_sprite(&sprite, 13, 13, false, true), source(6, 6));
+        assert_eq!(sample_sprite(&sprite, 0, 0, true, true), source(6, 0));
+    }
+
+    // 屏幕上 screen_x 列、v 行画出来的管身字符
+    fn pipe_texel(pipe_x: f32, screen_x: i32, v: i32) -> FontCharType {
+        let obstacle = Obstacle { x: pipe_x, ..Obstacle::new(0, 0, 0, &mut RandomNumberGenerator::seeded(SEED)) };
+        PipeGlyphs::Brick.texel(screen_x - obstacle.left(), v)
+    }
+
+    #[test]
+    fn brick_texels_follow_the_pipe() {
+        // 第一行是灰缝，整行都是管身字符
+        for u in 0..4 {
+            assert_eq!(PipeGlyphs::Brick.texel(u, 0), to_cp437('─'));
+        }
+        // 第一层砖的竖缝在第 0 列，第二层错开两列
+        assert_eq!(PipeGlyphs::Brick.texel(0, 1), to_cp437('│'));
+        assert_eq!(PipeGlyphs::Brick.texel(1, 1), to_cp437(' '));
+        assert_eq!(PipeGlyphs::Brick.texel(2, 4), to_cp437('│'));
+        assert_eq!(PipeGlyphs::Brick.texel(0, 4), to_cp437(' '));
+        assert_eq!(PipeGlyphs::Brick.texel(0, 7), to_cp437('│'));
+
+        // 花纹贴在管道上：管道往左挪一格，同一块砖也在左边一列
+        assert_eq!(pipe_texel(40.0, 40, 1), to_cp437('│'));
+        assert_eq!(pipe_texel(39.0, 39, 1), to_cp437('│'));
+        assert_eq!(pipe_texel(39.0, 40, 1), to_cp437(' '));
+        assert_eq!(pipe_texel(39.0, 41, 4), to_cp437('│'));
+        // 管道在两格之间时按 left() 取整
+        assert_eq!(pipe_texel(39.6, 39, 1), to_cp437('│'));
+    }
+
+    #[test]
+    fn other_pipe_styles_ignore_texel_position() {
+        for glyphs in [PipeGlyphs::Classic, PipeGlyphs::Solid, PipeGlyphs::Shade, PipeGlyphs::Hash] {
+            let (body, _) = glyphs.glyphs();
+            assert_eq!(glyphs.texel(1, 1), body);
+            assert_eq!(glyphs.texel(3, 5), body);
+        }
+    }
+
+    // 不开窗口的 BTerm，只用来带上帧时间
+    fn context(frame_time_ms: f32) -> BTerm {
+        BTerm {
+            width_pixels: 1200,
+            height_pixels: 800,
+            original_height_pixels: 800,
+            original_width_pixels: 1200,
+            fps: 60.0,
+            frame_time_ms,
+            active_console: 0,
+            key: None,
+            mouse_pos: (0, 0),
+            left_click: false,
+            shift: false,
+            control: false,
+            alt: false,
+            web_button: None,
+            quitting: false,
+            post_scanlines: false,
+            post_screenburn: false,
+            screen_burn_color: RGB::named(BLACK),
+            mouse_visible: true,
+        }
+    }
+
+    #[test]
+    fn spawn_x_ignores_single_frame_spikes_and_keeps_pipe_order() {
+        let mut state = test_state();
+        state.restart_with_seed(SEED);
+        let speed = 4.0;
+        // 只有一帧卡顿，平滑帧率不变，出现位置也不变
+        assert_eq!(state.spawn_x(&context(16.0), speed), state.spawn_x(&context(1.0), speed));
+
+        // 上一根管道还在更远的地方时，新管道不会出现在它左边
+        let far = DEFAULT_PARAMETERS.screen_width * 3;
+        state.run.obstacle_list.last_mut().expect("first pipe").x = far as f32;
+        let previous = state.run.obstacle_list.last().unwrap().right();
+        assert!(state.spawn_x(&context(16.0), speed) >= previous);
+    }
+
+    #[test]
+    fn window_size_is_checked_in_logical_pixels() {
+        let (min_width, min_height) = min_window_size();
+        let mut ctx = context(16.0);
+        ctx.width_pixels = min_width;
+        ctx.height_pixels = min_height;
+        assert!(!window_too_small(&ctx));
+        ctx.width_pixels = min_width - 1;
+        assert!(window_too_small(&ctx));
+    }
+
+    #[test]
+    fn rebinding_skips_the_key_used_by_the_other_action() {
+        let flap = KeyBindings::cycle_key(VirtualKeyCode::Space, &KeyBindings::FLAP_KEYS, VirtualKeyCode::Down, 1);
+        assert!(flap == VirtualKeyCode::Up);
+        // Z 已经给了快速下落，从 W 往后直接跳到 X
+        let flap = KeyBindings::cycle_key(VirtualKeyCode::W, &KeyBindings::FLAP_KEYS, VirtualKeyCode::Z, 1);
+        assert!(flap == VirtualKeyCode::X);
+        let flap = KeyBindings::cycle_key(VirtualKeyCode::Space, &KeyBindings::FLAP_KEYS, VirtualKeyCode::Down, -1);
+        assert!(flap == VirtualKeyCode::K);
+    }
+
+    #[test]
+    fn score_is_submitted_once_the_run_is_final() {
+        let mut state = test_state();
+        state.restart_with_seed(SEED);
+        state.run.time_ms = 3000.0;
+        state.run.rewind_buffer.push_back(state.snapshot());
+        state.game_over();
+        assert!(state.run.unsubmitted);
+        // 倒带复活以后这局还没结束，不能提交
+        state.rewind();
+        assert!(!state.run.unsubmitted);
+        state.game_over();
+        assert!(state.run.unsubmitted);
+        // 离开结束界面时提交一次，之后不会再提交第二次
+        assert!(state.submit_final_score().is_none(), "no submit URL configured");
+        assert!(!state.run.unsubmitted);
+    }
+
+    #[test]
+    fn default_world_ends_at_the_ground() {
+        assert_eq!(DEFAULT_PARAMETERS.validate(), Ok(()));
+        assert_eq!(DEFAULT_PARAMETERS.world_bottom, ground_top());
+    }
+
+    // 注册和正式运行时一样的两个控制台，测试里才能调用会画画面的函数；它们只是内存里的格子，不需要窗口
+    fn register_consoles() {
+        static CONSOLES: std::sync::Once = std::sync::Once::new();
+        CONSOLES.call_once(|| {
+            let mut ctx = context(16.0);
+            let (width, height) = (DEFAULT_PARAMETERS.screen_width as u32, DEFAULT_PARAMETERS.screen_height as u32);
+            ctx.register_console(SimpleConsole::init(width, height), 0);
+            ctx.register_console(SparseConsole::init(width, height), 0);
+        });
+    }
+
+    fn press(key: VirtualKeyCode) -> BTerm {
+        let mut ctx = context(16.0);
+        ctx.key = Some(key);
+        ctx
+    }
+
+    #[test]
+    fn photo_key_enters_photo_mode_and_leaves_on_the_next_press() {
+        register_consoles();
+        let mut state = test_state();
+        state.restart_with_seed(SEED);
+        state.mode = GameMode::Playing;
+        state.playing(&mut press(VirtualKeyCode::P));
+        assert!(matches!(state.mode, GameMode::Photo));
+        assert!(state.photo.is_some());
+
+        state.photo_mode(&mut press(VirtualKeyCode::P));
+        assert!(matches!(state.mode, GameMode::Playing));
+        assert!(state.photo.is_none());
+    }
+
+    #[test]
+    fn photo_mode_is_disabled_in_hot_seat_and_hardcore_runs() {
+        register_consoles();
+        let mut state = test_state();
+        state.restart_with_seed(SEED);
+        state.mode = GameMode::Playing;
+        state.hot_seat = Some(HotSeat { turn: 0, scores: [0; 2], seed: SEED });
+        state.playing(&mut press(VirtualKeyCode::P));
+        assert!(matches!(state.mode, GameMode::Playing));
+        assert!(state.photo.is_none());
+
+        state.hot_seat = None;
+        state.run.flags.difficulty = Difficulty::Hard;
+        state.run.flags.assists_used = false;
+        state.run.flags.practice = false;
+        assert!(state.run.flags.is_hardcore());
+        state.playing(&mut press(VirtualKeyCode::P));
+        assert!(matches!(state.mode, GameMode::Playing));
+        assert!(state.photo.is_none());
+    }
+
+    #[test]
+    fn player_faces_left_only_when_the_background_scrolls_left_to_right() {
+        let mut state = test_state();
+        for direction in [ScrollDirection::RightToLeft, ScrollDirection::LeftToRight, ScrollDirection::Vertical] {
+            state.settings.scroll_direction = direction;
+            assert_eq!(state.player_mirrored(), direction == ScrollDirection::LeftToRight);
+        }
+    }
+
+    #[test]
+    fn wind_zone_sits_midway_to_the_jittered_next_pipe() {
+        register_consoles();
+        let mut state = test_state();
+        state.settings.spacing_jitter = true;
+        state.restart_with_seed(SEED);
+        state.run.score = DEFAULT_PARAMETERS.wind_min_score * state.run.flags.difficulty.score_multiplier();
+        let mut checked = 0;
+        for _ in 0..100 {
+            state.run.wind_zones.clear();
+            state.run.distance = f32::MAX;
+            state.step(&mut context(16.0), false);
+            let (Some(zone), Some(obstacle)) = (state.run.wind_zones.last(), state.run.obstacle_list.last()) else {
+                continue;
+            };
+            // 下一根管道在距离这一根 obstacle_distance 加上新抽的间距处出现
+            let next_x = obstacle.x as i32 + state.settings.obstacle_distance + state.run.spacing_offset;
+            let before = zone.x as i32 - obstacle.right();
+            let after = next_x - zone.right();
+            assert!((before - after).abs() <= 1, "{before} vs {after}");
+            if state.run.spacing_offset != 0 {
+                checked += 1;
+            }
+        }
+        assert!(checked > 0);
+    }
+
+    #[test]
+    fn obstacle_preview_leaves_the_run_rng_alone() {
+        register_consoles();
+        let mut state = test_state();
+        state.restart_with_seed(SEED);
+        let mut rng = state.run.rng.clone();
+        for _ in 0..200 {
+            state.render_obstacle_preview(&mut context(16.0));
+        }
+        assert!(!state.preview_obstacles.is_empty());
+        assert_eq!(state.run.rng.next_u64(), rng.next_u64());
+    }
+
+    #[test]
+    fn frame_time_is_clamped() {
+        let min = DEFAULT_PARAMETERS.min_frame_time_ms;
+        let max = DEFAULT_PARAMETERS.max_frame_time_ms;
+        assert_eq!(frame_time_ms(&context(16.0)), 16.0);
+        assert_eq!(frame_time_ms(&context(0.0)), min);
+        assert_eq!(frame_time_ms(&context(-5.0)), min);
+        assert_eq!(frame_time_ms(&context(f32::NAN)), min);
+        assert_eq!(frame_time_ms(&context(f32::NEG_INFINITY)), min);
+        // 卡了几秒以后只按上限推进一帧
+        assert_eq!(frame_time_ms(&context(3000.0)), max);
+        assert_eq!(frame_time_ms(&context(f32::INFINITY)), max);
+        assert_eq!(frame_time_ms(&context(min)), min);
+        assert_eq!(frame_time_ms(&context(max)), max);
+    }
+
+    fn pixel_color(image: &image::DynamicImage, x: i32, y: i32) -> RGB {
+        let pixel = image.get_pixel(x as u32, y as u32);
+        RGB::from_u8(pixel[0], pixel[1], pixel[2])
+    }
+
+    #[test]
+    fn background_offset_wraps_across_zero() {
+        let cases = [
+            (ScrollDirection::LeftToRight, DEFAULT_PARAMETERS.screen_width),
+            (ScrollDirection::Vertical, DEFAULT_PARAMETERS.screen_height),
+        ];
+        for (direction, wrap) in cases {
+            let mut state = test_state();
+            state.settings.scroll_direction = direction;
+            state.run.background_offset = 0.05;
+            // 一帧最多走 0.1 格，从 0.05 往回走就会减到 0 以下
+            state.update_background(&mut context(DEFAULT_PARAMETERS.max_frame_time_ms));
+            let offset = state.run.background_offset;
+            assert!(offset >= wrap as f32 - 1.0 && offset < wrap as f32, "offset {} did not wrap to the far end", offset);
+
+            // 取样的位置也跟着绕到图片的另一头
+            let background = state.background_image();
+            let (width, height) = (background.width() as i32, background.height() as i32);
+            let expected = match direction {
+                ScrollDirection::Vertical => pixel_color(background, 0, (wrap - 1).rem_euclid(height)),
+                _ => pixel_color(background, (wrap - 1).rem_euclid(width), 0),
+            };
+            assert_eq!(state.sample_background(background, 0, 0), expected);
+        }
+    }
+
+    #[test]
+    fn background_offset_wraps_past_the_width() {
+        let mut state = test_state();
+        state.settings.scroll_direction = ScrollDirection::RightToLeft;
+        state.run.background_offset = DEFAULT_PARAMETERS.screen_width as f32 - 0.05;
+        state.update_background(&mut context(DEFAULT_PARAMETERS.max_frame_time_ms));
+        assert!((0.0..1.0).contains(&state.run.background_offset));
+    }
+
+    // 50x30 的背景，红、绿分量就是像素坐标
+    fn coordinate_background() -> image::DynamicImage {
+        image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(50, 30, |x, y| image::Rgba([x as u8, y as u8, 0, 255])))
+    }
+
+    #[test]
+    fn background_sampling_handles_boundary_and_negative_offsets() {
+        let state = test_state();
+        let background = coordinate_background();
+        let column = |x: i32, offset: f32| {
+            let color = state.background_color(&background, x, 0, offset);
+            (color.r * 255.0).round() as i32
+        };
+        assert_eq!(column(0, 0.0), 0);
+        assert_eq!(column(0, 49.0), 49);
+        assert_eq!(column(0, 49.9), 49);
+        assert_eq!(column(0, 50.0), 0);
+        assert_eq!(column(1, 49.0), 0);
+        assert_eq!(column(49, 1.0), 0);
+        assert_eq!(column(0, -1.0), 49);
+        assert_eq!(column(0, -50.0), 0);
+        assert_eq!(column(0, -51.0), 49);
+        assert_eq!(column(3, -1000.0), 3);
+        // 屏幕坐标本身为负时也落在图片里
+        assert_eq!(column(-1, 0.0), 49);
+    }
+
+    #[test]
+    fn vertical_background_sampling_wraps_rows() {
+        let mut state = test_state();
+        state.settings.scroll_direction = ScrollDirection::Vertical;
+        let background = coordinate_background();
+        let row = |y: i32, offset: f32| {
+            let color = state.background_color(&background, 0, y, offset);
+            (color.g * 255.0).round() as i32
+        };
+        assert_eq!(row(0, 29.0), 29);
+        assert_eq!(row(0, 30.0), 0);
+        assert_eq!(row(0, -1.0), 29);
+        assert_eq!(row(5, -31.0), 4);
+    }
+
+    #[test]
+    fn pooled_obstacle_matches_a_new_one() {
+        for seed in 0..20 {
+            for score in [0, 20, 35, 100] {
+                // 从池里取出的管道带着上一次用过的各种状态
+                let mut used = RandomNumberGenerator::seeded(seed + 1000);
+                let mut pooled = Obstacle::new(90, 60, -3, &mut used);
+                pooled.x = -2.5;
+                pooled.scored = true;
+                pooled.near_miss = true;
+                pooled.near_miss_awarded = true;
+                pooled.telegraph = true;
+                pooled.closing = Some(3.0);
+                pooled.second_gap_y = Some(10);
+
+                let mut pooled_rng = RandomNumberGenerator::seeded(seed);
+                let mut fresh_rng = RandomNumberGenerator::seeded(seed);
+                pooled.reset(120, score, -2, &mut pooled_rng);
+                let fresh = Obstacle::new(120, score, -2, &mut fresh_rng);
+
+                assert_eq!(pooled, fresh);
+                assert_eq!(pooled.gaps().collect::<Vec<_>>(), fresh.gaps().collect::<Vec<_>>());
+                // 两边用掉的随机数一样多，接下来的金币判定也一样
+                assert_eq!(
+                    pooled_rng.range(0, 100) < DEFAULT_PARAMETERS.coin_chance,
+                    fresh_rng.range(0, 100) < DEFAULT_PARAMETERS.coin_chance,
+                );
+                assert_eq!(pooled_rng.next_u64(), fresh_rng.next_u64());
+            }
+        }
+    }
+
+    #[test]
+    fn share_code_keeps_the_adaptive_level() {
+        for adaptive_level in [None, Some(-1.0), Some(-0.25), Some(0.0), Some(0.75), Some(1.0)] {
+            let code = ShareCode {
+                seed: u64::MAX - 7,
+                difficulty: Difficulty::Adaptive,
+                run_mode: RunMode::Marathon,
+                obstacle_distance: 55,
+                adaptive_level,
+                spacing_jitter: adaptive_level.is_some(),
+            };
+            let decoded = ShareCode::decode(&code.encode()).expect("valid code");
+            assert_eq!(decoded.seed, code.seed);
+            assert_eq!(decoded.obstacle_distance, 55);
+            assert_eq!(decoded.adaptive_level, adaptive_level);
+            assert_eq!(decoded.spacing_jitter, code.spacing_jitter);
+        }
+    }
+
+    #[test]
+    fn legacy_share_code_has_no_adaptive_level() {
+        let code = ShareCode {
+            seed: 123456789,
+            difficulty: Difficulty::Hard,
+            run_mode: RunMode::Classic,
+            obstacle_distance: 45,
+            adaptive_level: None,
+            spacing_jitter: true,
+        };
+        // 旧版的分享码就是去掉新增的最后一个数据字符、重新算校验位
+        let digits: Vec<u8> = code
+            .encode()
+            .chars()
+            .filter(|c| *c != '-')
+            .map(|c| ShareCode::ALPHABET.iter().position(|a| *a as char == c).unwrap() as u8)
+            .take(ShareCode::LEGACY_LEN - 1)
+            .collect();
+        let mut legacy: String = digits.iter().map(|d| ShareCode::ALPHABET[*d as usize] as char).collect();
+        legacy.push(ShareCode::ALPHABET[ShareCode::checksum(&digits) as usize] as char);
+
+        let decoded = ShareCode::decode(&legacy).expect("legacy codes still decode");
+        assert_eq!(decoded.seed, 123456789);
+        assert!(decoded.difficulty == Difficulty::Hard);
+        assert_eq!(decoded.obstacle_distance, 45);
+        assert_eq!(decoded.adaptive_level, None);
+        assert!(decoded.spacing_jitter);
+    }
+}